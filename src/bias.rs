@@ -0,0 +1,148 @@
+use std::fmt;
+use crate::stats;
+
+/// Significance level for deciding whether permuting pixels changed the error rate.
+pub const ALPHA: f64 = 0.05;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bias {
+    /// Permuting pixels significantly increases the error rate, so the variant relies on
+    /// spatial relationships between pixels.
+    Positive,
+    /// Permuting pixels significantly decreases the error rate.
+    Negative,
+    None,
+}
+
+impl fmt::Display for Bias {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bias::Positive => write!(f, "positive bias"),
+            Bias::Negative => write!(f, "negative bias"),
+            Bias::None => write!(f, "no bias"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Significance {
+    /// Exact McNemar test on per-image outcomes. `improved` counts images misclassified
+    /// originally but not after permuting; `worsened` counts the reverse.
+    McNemar {improved: usize, worsened: usize, p_value: f64},
+    /// Paired t test on the error rates of each trial.
+    PairedT {t: f64, critical: f64},
+}
+
+impl Significance {
+    pub fn mcnemar(original_correct: &[bool], permuted_correct: &[bool]) -> Significance {
+        assert_eq!(original_correct.len(), permuted_correct.len());
+        let pairs = || original_correct.iter().zip(permuted_correct.iter());
+        let improved = pairs().filter(|(o, p)| !**o && **p).count();
+        let worsened = pairs().filter(|(o, p)| **o && !**p).count();
+        Significance::McNemar {improved, worsened, p_value: mcnemar_p_value(improved, worsened)}
+    }
+
+    pub fn paired_t(original_errors: &[f64], permuted_errors: &[f64]) -> Significance {
+        assert_eq!(original_errors.len(), permuted_errors.len());
+        let differences: Vec<f64> = permuted_errors.iter().zip(original_errors.iter()).map(|(p, o)| p - o).collect();
+        let mean = stats::mean(&differences);
+        let standard_error = stats::stdev(&differences) / (differences.len() as f64).sqrt();
+        let t = if mean == 0.0 {0.0} else {mean / standard_error};
+        Significance::PairedT {t, critical: stats::t_critical_95(differences.len() - 1)}
+    }
+
+    pub fn is_significant(&self) -> bool {
+        match self {
+            Significance::McNemar {p_value, ..} => *p_value < ALPHA,
+            Significance::PairedT {t, critical} => t.abs() > *critical,
+        }
+    }
+}
+
+impl fmt::Display for Significance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Significance::McNemar {improved, worsened, p_value} =>
+                write!(f, "McNemar: {} improved, {} worsened, p = {:.4}", improved, worsened, p_value),
+            Significance::PairedT {t, critical} =>
+                write!(f, "paired t = {:.3}, critical value {:.3}", t, critical),
+        }
+    }
+}
+
+/// Two-sided exact binomial p-value for `b` and `c` discordant pairs, computed in log space
+/// so that large test sets do not underflow.
+pub fn mcnemar_p_value(b: usize, c: usize) -> f64 {
+    let n = b + c;
+    let mut log_choose = 0.0;
+    let mut tail = 0.0;
+    for i in 0..=b.min(c) {
+        if i > 0 {
+            log_choose += ((n - i + 1) as f64).ln() - (i as f64).ln();
+        }
+        tail += (log_choose - n as f64 * 2f64.ln()).exp();
+    }
+    (2.0 * tail).min(1.0)
+}
+
+#[derive(Clone)]
+pub struct BiasReport {
+    pub original_error: f64,
+    pub permuted_error: f64,
+    pub significance: Significance,
+}
+
+impl BiasReport {
+    pub fn difference(&self) -> f64 {
+        self.permuted_error - self.original_error
+    }
+
+    pub fn bias(&self) -> Bias {
+        if !self.significance.is_significant() || self.difference() == 0.0 {
+            Bias::None
+        } else if self.difference() > 0.0 {
+            Bias::Positive
+        } else {
+            Bias::Negative
+        }
+    }
+}
+
+impl fmt::Display for BiasReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "original {}%, permuted {}%, difference {:+}% ({}): {}",
+               self.original_error, self.permuted_error, self.difference(), self.significance, self.bias())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mcnemar() {
+        assert_eq!(1.0, mcnemar_p_value(0, 0));
+        assert!((mcnemar_p_value(0, 5) - 0.0625).abs() < 1e-12);
+        assert!((mcnemar_p_value(1, 9) - 22.0 / 1024.0).abs() < 1e-12);
+        assert!(mcnemar_p_value(400, 600) < 1e-9);
+        assert_eq!(1.0, mcnemar_p_value(300, 300));
+
+        let original = [true, true, true, false, true, true, true, true, true, true];
+        let permuted = [false, false, false, false, true, false, false, false, false, true];
+        let report = BiasReport {original_error: 10.0, permuted_error: 80.0, significance: Significance::mcnemar(&original, &permuted)};
+        assert_eq!(Significance::McNemar {improved: 0, worsened: 7, p_value: mcnemar_p_value(0, 7)}, report.significance);
+        assert_eq!(Bias::Positive, report.bias());
+    }
+
+    #[test]
+    fn test_paired_t() {
+        let significance = Significance::paired_t(&[10.0, 11.0, 12.0], &[5.0, 6.5, 6.0]);
+        assert!(significance.is_significant());
+        let report = BiasReport {original_error: 11.0, permuted_error: 5.833, significance};
+        assert_eq!(Bias::Negative, report.bias());
+
+        let unchanged = Significance::paired_t(&[10.0, 11.0, 12.0], &[11.0, 10.0, 12.0]);
+        assert!(!unchanged.is_significant());
+        assert_eq!(Significance::PairedT {t: 0.0, critical: 4.303}, unchanged);
+    }
+}
@@ -35,6 +35,15 @@ impl BitArray {
         self.bits[get_word(index)] & get_mask(index) > 0
     }
 
+    pub fn words(&self) -> &[u64] {
+        &self.bits
+    }
+
+    pub fn from_words(bits: Vec<u64>, size: u64) -> Self {
+        assert_eq!(bits.len() as u64, size.div_ceil(NUM_BITS));
+        BitArray {bits, size}
+    }
+
     pub fn count_bits_on(&self) -> u32 {
         self.bits.iter().map(|word| word.count_ones() as u32).sum()
     }
@@ -64,6 +73,20 @@ pub fn distance(b1: &BitArray, b2: &BitArray) -> u32 {
     (b1 ^ b2).count_bits_on()
 }
 
+/// `distance`, unless it exceeds `bound`. It stops counting differing bits as soon as
+/// it does, and returns `None`.
+pub fn distance_within(b1: &BitArray, b2: &BitArray, bound: u32) -> Option<u32> {
+    assert_eq!(b1.len(), b2.len());
+    let mut count = 0;
+    for (w1, w2) in b1.bits.iter().zip(b2.bits.iter()) {
+        count += (w1 ^ w2).count_ones();
+        if count > bound {
+            return None;
+        }
+    }
+    Some(count)
+}
+
 impl BitXor for &BitArray {
     type Output = BitArray;
 
@@ -113,6 +136,8 @@ mod tests {
         let b3 = &b ^ &b2;
         assert_eq!((b.len() as u32 / 2) + 1, b3.count_bits_on());
         assert_eq!(b3.count_bits_on(), distance(&b, &b2));
+        assert_eq!(Some(distance(&b, &b2)), distance_within(&b, &b2, distance(&b, &b2)));
+        assert_eq!(None, distance_within(&b, &b2, distance(&b, &b2) - 1));
 
         assert_ne!(b, b2);
         assert_ne!(b2, b3);
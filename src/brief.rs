@@ -1,18 +1,21 @@
 use crate::mnist_data::{Image, ImageIterator, Grid};
 use rand_distr::{Normal, Distribution};
-use rand::prelude::ThreadRng;
+use rand::Rng;
 use crate::bits::BitArray;
 use rand::distributions::Uniform;
 use crate::hash_histogram::HashHistogram;
 
+/// The coordinates of two pixels whose values are compared.
+pub type Pair = ((usize,usize),(usize,usize));
+
 #[derive(Clone)]
 pub struct Descriptor {
-    pairs: Vec<((usize,usize),(usize,usize))>,
+    pairs: Vec<Pair>,
     width: usize,
     height: usize
 }
 
-fn constrained_random(dist: &Normal<f64>, rng: &mut ThreadRng, max: usize) -> usize {
+fn constrained_random<R: Rng>(dist: &Normal<f64>, rng: &mut R, max: usize) -> usize {
     let mut value = dist.sample(rng);
     value = value.max(0 as f64);
     value = value.min((max - 1) as f64);
@@ -20,54 +23,51 @@ fn constrained_random(dist: &Normal<f64>, rng: &mut ThreadRng, max: usize) -> us
 }
 
 impl Descriptor {
-    pub fn classic_gaussian_brief(n: usize, width: usize, height: usize) -> Descriptor {
-        let mut rng = rand::thread_rng();
+    pub fn classic_gaussian_brief<R: Rng>(n: usize, width: usize, height: usize, rng: &mut R) -> Descriptor {
         let x_dist = Normal::new((width/2) as f64, (width/6) as f64).unwrap();
         let y_dist = Normal::new((height/2) as f64, (height/6) as f64).unwrap();
         let mut result = Descriptor {pairs: Vec::new(), width, height};
         for _ in 0..n {
-            result.pairs.push(((constrained_random(&x_dist, &mut rng, width),
-                                constrained_random(&y_dist, &mut rng, height)),
-                              (constrained_random(&x_dist, &mut rng, width),
-                                constrained_random(&y_dist, &mut rng, height))));
+            result.pairs.push(((constrained_random(&x_dist, rng, width),
+                                constrained_random(&y_dist, rng, height)),
+                              (constrained_random(&x_dist, rng, width),
+                                constrained_random(&y_dist, rng, height))));
         }
         result
     }
 
-    pub fn classic_uniform_brief(n: usize, width: usize, height: usize) -> Descriptor {
-        let mut rng = rand::thread_rng();
+    pub fn classic_uniform_brief<R: Rng>(n: usize, width: usize, height: usize, rng: &mut R) -> Descriptor {
         let x_dist = Uniform::new(0, width);
         let y_dist = Uniform::new(0, height);
         let mut result = Descriptor {pairs: Vec::new(), width, height};
         for _ in 0..n {
-            result.pairs.push(((x_dist.sample(&mut rng), y_dist.sample(&mut rng)),
-                              (x_dist.sample(&mut rng), y_dist.sample(&mut rng))));
+            result.pairs.push(((x_dist.sample(rng), y_dist.sample(rng)),
+                              (x_dist.sample(rng), y_dist.sample(rng))));
         }
         result
     }
 
-    pub fn uniform_neighbor(neighbors: usize, width: usize, height: usize) -> Descriptor {
-        let mut rng = rand::thread_rng();
+    pub fn uniform_neighbor<R: Rng>(neighbors: usize, width: usize, height: usize, rng: &mut R) -> Descriptor {
         let x_dist = Uniform::new(0, width);
         let y_dist = Uniform::new(0, height);
         let mut result = Descriptor {pairs: Vec::new(), width, height};
         ImageIterator::new(0, 0, width, height, 1)
             .for_each(|(x, y)|
                 for _ in 0..neighbors {
-                    result.pairs.push(((x, y), (x_dist.sample(&mut rng), y_dist.sample(&mut rng))));
+                    result.pairs.push(((x, y), (x_dist.sample(rng), y_dist.sample(rng))));
                 });
         result
     }
 
-    pub fn gaussian_neighbor(neighbors: usize, stdev: usize, width: usize, height: usize) -> Descriptor {
+    pub fn gaussian_neighbor<R: Rng>(neighbors: usize, stdev: usize, width: usize, height: usize, rng: &mut R) -> Descriptor {
         let x_dist = Normal::new(0 as f64, stdev as f64).unwrap();
         let y_dist = Normal::new(0 as f64, stdev as f64).unwrap();
         let mut result = Descriptor {pairs: Vec::new(), width, height};
         ImageIterator::new(0, 0, width, height, 1)
             .for_each(|(x, y)|
                 for _ in 0..neighbors {
-                    let x_other = random_bounded_normal_value(&x_dist, x, 0, width);
-                    let y_other = random_bounded_normal_value(&y_dist, y, 0, height);
+                    let x_other = random_bounded_normal_value(&x_dist, x, 0, width, rng);
+                    let y_other = random_bounded_normal_value(&y_dist, y, 0, height, rng);
                     assert!(x_other < width);
                     assert!(y_other < height);
                     result.pairs.push(((x, y), (x_other, y_other)));
@@ -84,6 +84,14 @@ impl Descriptor {
         result
     }
 
+    pub fn from_pairs(pairs: Vec<Pair>, width: usize, height: usize) -> Descriptor {
+        Descriptor {pairs, width, height}
+    }
+
+    pub fn pairs(&self) -> &[Pair] {
+        &self.pairs
+    }
+
     pub fn width(&self) -> usize {
         self.width
     }
@@ -93,8 +101,8 @@ impl Descriptor {
     }
 
     pub fn apply_to(&self, img: &Image) -> BitArray {
-        assert_eq!(img.side(), self.width());
-        assert_eq!(img.side(), self.height());
+        assert_eq!(img.width(), self.width());
+        assert_eq!(img.height(), self.height());
 
         let mut bits = BitArray::new();
         self.pairs.iter()
@@ -120,14 +128,13 @@ impl Descriptor {
     }
 }
 
-pub fn random_bounded_normal_value(dist: &Normal<f64>, start_value: usize, min: usize, max: usize) -> usize {
-    let mut rng = ThreadRng::default();
-    let sample = dist.sample(&mut rng).abs() as usize;
+pub fn random_bounded_normal_value<R: Rng>(dist: &Normal<f64>, start_value: usize, min: usize, max: usize, rng: &mut R) -> usize {
+    let sample = dist.sample(rng).abs() as usize;
     let min_diff = start_value - min;
     let max_diff = max - start_value;
 
     if sample < min_diff && sample < max_diff {
-        if rand::random() {
+        if rng.gen() {
             start_value + sample
         } else {
             start_value - sample
@@ -136,7 +143,7 @@ pub fn random_bounded_normal_value(dist: &Normal<f64>, start_value: usize, min:
         start_value - sample
     } else if sample < max_diff {
         start_value + sample
-    } else if rand::random() {
+    } else if rng.gen() {
         min
     } else {
         max - 1
@@ -0,0 +1,307 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use crate::mnist_data::{Image, Grid};
+use crate::bits::BitArray;
+use crate::brief::Descriptor;
+use crate::idx::invalid_data;
+
+const MAGIC: &[u8; 8] = b"FLAIRS33";
+const VERSION: u32 = 2;
+
+/// A value that can be written to and read back from the binary cache format.
+pub trait Cacheable: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(input: &mut Decoder) -> io::Result<Self>;
+}
+
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl <'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Decoder {bytes, pos: 0}
+    }
+
+    pub fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.pos + n > self.bytes.len() {
+            return Err(invalid_data(format!("cache data ends after {} bytes; needed {} more", self.bytes.len(), self.pos + n - self.bytes.len())));
+        }
+        let result = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(result)
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        let b = self.take(8)?;
+        Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    pub fn usize(&mut self) -> io::Result<usize> {
+        Ok(self.u64()? as usize)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+}
+
+fn encode_u64(value: u64, out: &mut Vec<u8>) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+impl Cacheable for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+
+    fn decode(input: &mut Decoder) -> io::Result<Self> {
+        Ok(input.take(1)?[0])
+    }
+}
+
+impl Cacheable for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_u64(*self as u64, out);
+    }
+
+    fn decode(input: &mut Decoder) -> io::Result<Self> {
+        input.usize()
+    }
+}
+
+impl <A: Cacheable, B: Cacheable> Cacheable for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> io::Result<Self> {
+        let a = A::decode(input)?;
+        Ok((a, B::decode(input)?))
+    }
+}
+
+impl <T: Cacheable> Cacheable for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        self.iter().for_each(|item| item.encode(out));
+    }
+
+    fn decode(input: &mut Decoder) -> io::Result<Self> {
+        let len = input.usize()?;
+        (0..len).map(|_| T::decode(input)).collect()
+    }
+}
+
+impl Cacheable for Image {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.width().encode(out);
+        self.height().encode(out);
+        self.planes().encode(out);
+        self.x_y_iter().for_each(|(x, y)| out.push(self.get(x, y)));
+    }
+
+    fn decode(input: &mut Decoder) -> io::Result<Self> {
+        let width = input.usize()?;
+        let height = input.usize()?;
+        let planes = input.usize()?;
+        if planes == 0 || !height.is_multiple_of(planes) {
+            return Err(invalid_data(format!("{} rows cannot be split into {} planes", height, planes)));
+        }
+        Ok(Image::from_pixels(width, height, input.take(width * height)?.to_vec()).with_planes(planes))
+    }
+}
+
+impl Cacheable for BitArray {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_u64(self.len(), out);
+        self.words().iter().for_each(|word| encode_u64(*word, out));
+    }
+
+    fn decode(input: &mut Decoder) -> io::Result<Self> {
+        let size = input.u64()?;
+        let num_words = (size as usize).div_ceil(64);
+        let words = (0..num_words).map(|_| input.u64()).collect::<io::Result<Vec<u64>>>()?;
+        Ok(BitArray::from_words(words, size))
+    }
+}
+
+impl Cacheable for Descriptor {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.width().encode(out);
+        self.height().encode(out);
+        self.pairs().len().encode(out);
+        for ((x1, y1), (x2, y2)) in self.pairs() {
+            [x1, y1, x2, y2].iter().for_each(|v| v.encode(out));
+        }
+    }
+
+    fn decode(input: &mut Decoder) -> io::Result<Self> {
+        let width = input.usize()?;
+        let height = input.usize()?;
+        let num_pairs = input.usize()?;
+        let mut pairs = Vec::new();
+        for _ in 0..num_pairs {
+            pairs.push(((input.usize()?, input.usize()?), (input.usize()?, input.usize()?)));
+        }
+        Ok(Descriptor::from_pairs(pairs, width, height))
+    }
+}
+
+/// 64-bit FNV-1a, used both as the file checksum and to derive file names from keys.
+pub fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn fingerprint<T: Cacheable>(value: &T) -> u64 {
+    let mut bytes = Vec::new();
+    value.encode(&mut bytes);
+    checksum(&bytes)
+}
+
+/// Identifies a source file by its path, size and modification time, so that cached
+/// datasets are rebuilt whenever the underlying files change.
+pub fn file_signature(file_name: &str) -> io::Result<String> {
+    let metadata = fs::metadata(file_name)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    Ok(format!("{}[{}@{}]", file_name, metadata.len(), modified))
+}
+
+pub fn encode_entry<T: Cacheable>(key: &str, value: &T) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    key.len().encode(&mut bytes);
+    bytes.extend_from_slice(key.as_bytes());
+    value.encode(&mut bytes);
+    let sum = checksum(&bytes);
+    encode_u64(sum, &mut bytes);
+    bytes
+}
+
+/// Returns `Ok(None)` if the entry was written for a different key.
+pub fn decode_entry<T: Cacheable>(key: &str, bytes: &[u8]) -> io::Result<Option<T>> {
+    if bytes.len() < MAGIC.len() + 4 + 8 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(invalid_data("not a flairs33 cache file".to_string()));
+    }
+    let (contents, sum) = bytes.split_at(bytes.len() - 8);
+    if Decoder::new(sum).u64()? != checksum(contents) {
+        return Err(invalid_data("cache checksum mismatch".to_string()));
+    }
+    let mut input = Decoder::new(&contents[MAGIC.len()..]);
+    let version = input.take(4)?;
+    if u32::from_le_bytes([version[0], version[1], version[2], version[3]]) != VERSION {
+        return Err(invalid_data("unsupported cache version".to_string()));
+    }
+    let key_len = input.usize()?;
+    if input.take(key_len)? != key.as_bytes() {
+        return Ok(None);
+    }
+    let value = T::decode(&mut input)?;
+    if input.is_finished() {
+        Ok(Some(value))
+    } else {
+        Err(invalid_data("trailing bytes after cached value".to_string()))
+    }
+}
+
+#[derive(Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: &str) -> io::Result<Cache> {
+        fs::create_dir_all(dir)?;
+        Ok(Cache {dir: Path::new(dir).to_path_buf()})
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.cache", checksum(key.as_bytes())))
+    }
+
+    /// A missing, stale or corrupted entry is reported and treated as absent.
+    pub fn load<T: Cacheable>(&self, key: &str) -> Option<T> {
+        let path = self.path_for(key);
+        let bytes = fs::read(&path).ok()?;
+        match decode_entry(key, &bytes) {
+            Ok(value) => value,
+            Err(e) => {
+                println!("Ignoring cache file {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Failing to write the entry is reported, but is not an error: the value can still be used.
+    pub fn store<T: Cacheable>(&self, key: &str, value: &T) {
+        let path = self.path_for(key);
+        if let Err(e) = fs::write(&path, encode_entry(key, value)) {
+            println!("Could not write cache file {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn load_or_else<T: Cacheable, F: FnOnce() -> T>(&self, key: &str, build: F) -> T {
+        match self.load(key) {
+            Some(value) => {
+                println!("Loaded {} from cache", key);
+                value
+            }
+            None => {
+                let value = build();
+                self.store(key, &value);
+                value
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let images = vec![(3u8, Image::from_pixels(3, 2, vec![1, 2, 3, 4, 5, 6])), (7u8, Image::from_pixels(1, 1, vec![9])),
+                          (5u8, Image::from_pixels(1, 2, vec![8, 9]).with_planes(2))];
+        let bytes = encode_entry("images", &images);
+        assert_eq!(Some(images), decode_entry("images", &bytes).unwrap());
+        assert_eq!(None, decode_entry::<Vec<(u8,Image)>>("other", &bytes).unwrap());
+
+        let mut bits = BitArray::new();
+        (0..70).for_each(|i| bits.add(i % 3 == 0));
+        let converted = vec![(1u8, bits.clone()), (2u8, bits)];
+        assert_eq!(Some(converted.clone()), decode_entry("bits", &encode_entry("bits", &converted)).unwrap());
+
+        let kernelized = vec![(0u8, vec![Image::from_pixels(2, 1, vec![4, 5]), Image::from_pixels(1, 2, vec![6, 7])])];
+        assert_eq!(Some(kernelized.clone()), decode_entry("kernels", &encode_entry("kernels", &kernelized)).unwrap());
+
+        let descriptor = Descriptor::equidistant(4, 3, 1, 2);
+        let decoded: Descriptor = decode_entry("d", &encode_entry("d", &descriptor)).unwrap().unwrap();
+        assert_eq!(descriptor.pairs(), decoded.pairs());
+        assert_eq!((4, 3), (decoded.width(), decoded.height()));
+    }
+
+    #[test]
+    fn test_corruption() {
+        let mut bytes = encode_entry("images", &vec![(3u8, Image::from_pixels(2, 1, vec![1, 2]))]);
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 1;
+        assert!(decode_entry::<Vec<(u8,Image)>>("images", &bytes).is_err());
+        assert!(decode_entry::<Vec<(u8,Image)>>("images", b"not a cache").is_err());
+    }
+
+    #[test]
+    fn test_cache_dir() -> io::Result<()> {
+        let dir = "test_cache_dir";
+        let cache = Cache::new(dir)?;
+        assert_eq!(None, cache.load::<Vec<usize>>("numbers"));
+        assert_eq!(vec![1, 2, 3], cache.load_or_else("numbers", || vec![1usize, 2, 3]));
+        assert_eq!(vec![1, 2, 3], cache.load_or_else("numbers", || vec![4usize]));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use std::io;
+use crate::mnist_data::Image;
+use crate::idx::invalid_data;
+
+pub const CIFAR_SIDE: usize = 32;
+pub const CIFAR_PLANE_BYTES: usize = CIFAR_SIDE * CIFAR_SIDE;
+pub const CIFAR_RECORD_BYTES: usize = 1 + 3 * CIFAR_PLANE_BYTES;
+pub const CIFAR_LABELS: u8 = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// One 32x32 plane of ITU-R BT.601 luminance.
+    Grayscale,
+    /// The red, green and blue planes stacked top to bottom in a single 32x96 image of three planes.
+    Channels,
+}
+
+pub fn read_cifar_file(file_name: &str, mode: ColorMode) -> io::Result<Vec<(u8,Image)>> {
+    let bytes = fs::read(file_name)?;
+    parse_cifar(&bytes, mode).map_err(|e| invalid_data(format!("{}: {}", file_name, e)))
+}
+
+pub fn parse_cifar(bytes: &[u8], mode: ColorMode) -> io::Result<Vec<(u8,Image)>> {
+    if !bytes.len().is_multiple_of(CIFAR_RECORD_BYTES) {
+        return Err(invalid_data(format!("{} bytes is not a multiple of the {}-byte CIFAR-10 record size", bytes.len(), CIFAR_RECORD_BYTES)));
+    }
+    bytes.chunks(CIFAR_RECORD_BYTES)
+        .map(|record| {
+            let label = record[0];
+            if label >= CIFAR_LABELS {
+                Err(invalid_data(format!("label {} is not a CIFAR-10 label", label)))
+            } else {
+                Ok((label, record_image(&record[1..], mode)))
+            }
+        })
+        .collect()
+}
+
+fn record_image(planes: &[u8], mode: ColorMode) -> Image {
+    match mode {
+        ColorMode::Channels => Image::from_pixels(CIFAR_SIDE, 3 * CIFAR_SIDE, planes.to_vec()).with_planes(3),
+        ColorMode::Grayscale => {
+            let (red, rest) = planes.split_at(CIFAR_PLANE_BYTES);
+            let (green, blue) = rest.split_at(CIFAR_PLANE_BYTES);
+            Image::from_pixels(CIFAR_SIDE, CIFAR_SIDE, (0..CIFAR_PLANE_BYTES)
+                .map(|i| luminance(red[i], green[i], blue[i]))
+                .collect())
+        }
+    }
+}
+
+pub fn luminance(red: u8, green: u8, blue: u8) -> u8 {
+    (0.299 * red as f64 + 0.587 * green as f64 + 0.114 * blue as f64).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mnist_data::Grid;
+
+    fn record(label: u8, red: u8, green: u8, blue: u8) -> Vec<u8> {
+        let mut result = vec![label];
+        for value in [red, green, blue].iter() {
+            result.extend((0..CIFAR_PLANE_BYTES).map(|_| *value));
+        }
+        result
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut bytes = record(3, 255, 0, 0);
+        bytes.append(&mut record(9, 10, 20, 30));
+
+        let gray = parse_cifar(&bytes, ColorMode::Grayscale).unwrap();
+        assert_eq!(2, gray.len());
+        assert_eq!(3, gray[0].0);
+        assert_eq!(CIFAR_SIDE, gray[0].1.width());
+        assert_eq!(CIFAR_SIDE, gray[0].1.height());
+        assert_eq!(luminance(255, 0, 0), gray[0].1.get(5, 5));
+        assert_eq!(18, gray[1].1.get(31, 31));
+
+        let channels = parse_cifar(&bytes, ColorMode::Channels).unwrap();
+        assert_eq!(9, channels[1].0);
+        assert_eq!(3 * CIFAR_SIDE, channels[1].1.height());
+        assert_eq!(3, channels[1].1.planes());
+        assert_eq!(10, channels[1].1.get(0, 0));
+        assert_eq!(20, channels[1].1.get(0, CIFAR_SIDE));
+        assert_eq!(30, channels[1].1.get(0, 2 * CIFAR_SIDE));
+    }
+
+    #[test]
+    fn test_bad_records() {
+        assert!(parse_cifar(&record(0, 1, 2, 3)[1..], ColorMode::Grayscale).is_err());
+        assert!(parse_cifar(&record(10, 1, 2, 3), ColorMode::Grayscale).is_err());
+    }
+}
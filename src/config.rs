@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use crate::options::invalid_input;
+
+/// A study defined in a configuration file. The `[options]` section gives command-line
+/// arguments: `name = value` for the option `--name value` and a bare `name` for a flag.
+/// Each `[variant name]` section gives the settings of one variant. Lines starting with
+/// `#` are comments.
+#[derive(Debug, PartialEq)]
+pub struct Config {
+    pub options: Vec<String>,
+    pub variants: Vec<(String,BTreeMap<String,String>)>,
+}
+
+pub fn read_config(file_name: &str) -> io::Result<Config> {
+    let text = fs::read_to_string(file_name)?;
+    parse_config(&text).map_err(|e| invalid_input(format!("{}: {}", file_name, e)))
+}
+
+enum Part {
+    Options,
+    Variant,
+}
+
+pub fn parse_config(text: &str) -> io::Result<Config> {
+    let mut config = Config {options: Vec::new(), variants: Vec::new()};
+    let mut part = None;
+    let mut option_names = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let error = |msg: String| invalid_input(format!("line {}: {}", i + 1, msg));
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            let header = line.strip_suffix(']').ok_or_else(|| error(format!("unclosed section header \"{}\"", line)))?[1..].trim();
+            part = if header == "options" {
+                Some(Part::Options)
+            } else if let Some(name) = header.strip_prefix("variant ") {
+                let name = name.trim();
+                if config.variants.iter().any(|(n, _)| n == name) {
+                    return Err(error(format!("variant {} is defined more than once", name)));
+                }
+                config.variants.push((name.to_string(), BTreeMap::new()));
+                Some(Part::Variant)
+            } else {
+                return Err(error(format!("unknown section \"{}\"; expected [options] or [variant name]", header)));
+            };
+            continue;
+        }
+
+        let (key, value) = match line.find('=') {
+            Some(j) => (line[..j].trim(), Some(line[j + 1..].trim())),
+            None => (line, None)
+        };
+        match (&part, value) {
+            (None, _) => return Err(error("settings must follow [options] or [variant name]".to_string())),
+            (Some(Part::Options), _) if option_names.contains(&key) => return Err(error(format!("option {} is given more than once", key))),
+            (Some(Part::Options), Some(value)) => {
+                config.options.push(format!("--{}", key));
+                config.options.push(value.to_string());
+                option_names.push(key);
+            }
+            (Some(Part::Options), None) => {
+                config.options.push(key.to_string());
+                option_names.push(key);
+            }
+            (Some(Part::Variant), None) => return Err(error(format!("\"{}\" needs a value", key))),
+            (Some(Part::Variant), Some(value)) => {
+                let (name, settings) = config.variants.last_mut().unwrap();
+                if settings.insert(key.to_string(), value.to_string()).is_some() {
+                    return Err(error(format!("variant {} gives {} more than once", name, key)));
+                }
+            }
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = parse_config("# A study\n\
+                                   [options]\n\
+                                   dataset = fashion\n\
+                                   permute\n\
+                                   \n\
+                                   [variant small brief]\n\
+                                   features = gaussian_brief\n\
+                                   pairs = 1000\n\
+                                   [variant raw]\n\
+                                   features=raw\n").unwrap();
+        assert_eq!(vec!["--dataset", "fashion", "permute"], config.options);
+        assert_eq!(2, config.variants.len());
+        assert_eq!("small brief", config.variants[0].0);
+        assert_eq!(Some(&"1000".to_string()), config.variants[0].1.get("pairs"));
+        assert_eq!(Some(&"raw".to_string()), config.variants[1].1.get("features"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse_config("features = raw\n").is_err());
+        assert!(parse_config("[variant a]\nfeatures = raw\n[variant a]\n").is_err());
+        assert!(parse_config("[variant a]\nfeatures = raw\nfeatures = patch\n").is_err());
+        assert!(parse_config("[variant a]\nfeatures\n").is_err());
+        assert!(parse_config("[options]\nk = 3\nk = 5\n").is_err());
+        assert!(parse_config("[study]\n").is_err());
+        let message = parse_config("[options\n").unwrap_err().to_string();
+        assert!(message.starts_with("line 1:"), "{}", message);
+    }
+}
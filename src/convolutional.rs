@@ -1,17 +1,20 @@
 use crate::mnist_data::{Image, image_mean, Grid};
 use crate::kmeans;
 use decorum::R64;
-use crate::euclidean_distance::euclidean_distance;
+use crate::euclidean_distance::{euclidean_distance, squared_differences_within};
+use rand::Rng;
+use crate::parallel;
 
 const NUM_KERNELS: usize = 8;
 const KERNEL_SIZE: usize = 3;
 const STRIDE: usize = 2;
 
-pub fn kernelize_all(labeled_images: &Vec<(u8,Image)>, levels: usize) -> Vec<(u8,Vec<Image>)> {
-    let kernels = extract_kernels_from(&(labeled_images.iter().map(|(_,img)| img.clone()).collect()), NUM_KERNELS, KERNEL_SIZE);
+/// Projects the images on `threads` threads once the kernels are chosen.
+pub fn kernelize_all<R: Rng>(labeled_images: &Vec<(u8,Image)>, levels: usize, threads: usize, rng: &mut R) -> Vec<(u8,Vec<Image>)> {
+    let kernels = extract_kernels_from(&(labeled_images.iter().map(|(_,img)| img.clone()).collect()), NUM_KERNELS, KERNEL_SIZE, rng);
     let mut kernelized: Vec<(u8,Vec<Image>)> = labeled_images.iter().map(|(label, img)| (*label, vec![img.clone()])).collect();
     for _ in 0..levels {
-        kernelized = kernelized.iter().map(|(label, images)| (*label, project_all_through(images, &kernels))).collect();
+        kernelized = parallel::map(&kernelized, threads, |(label, images)| (*label, project_all_through(images, &kernels)));
     }
     kernelized
 }
@@ -21,12 +24,23 @@ pub fn kernelized_distance(k1: &Vec<Image>, k2: &Vec<Image>) -> R64 {
     (0..k1.len()).map(|i| euclidean_distance(&k1[i], &k2[i])).sum()
 }
 
-pub fn extract_kernels_from(images: &Vec<Image>, num_kernels: usize, kernel_size: usize) -> Vec<Image> {
+/// `kernelized_distance`, unless it exceeds `bound`, as in `euclidean_distance_within`.
+#[allow(clippy::ptr_arg)] // Matches knn::BoundedDistance for kernelized images.
+pub fn kernelized_distance_within(k1: &Vec<Image>, k2: &Vec<Image>, bound: R64) -> Option<R64> {
+    assert_eq!(k1.len(), k2.len());
+    let mut sum = 0.0;
+    for i in 0..k1.len() {
+        sum += squared_differences_within(&k1[i], &k2[i], sum, bound.into_inner())?;
+    }
+    Some(R64::from_inner(sum))
+}
+
+pub fn extract_kernels_from<R: Rng>(images: &Vec<Image>, num_kernels: usize, kernel_size: usize, rng: &mut R) -> Vec<Image> {
     let mut candidates = Vec::new();
     for img in images.iter() {
         add_kernels_from_to(img, &mut candidates, kernel_size);
     }
-    kmeans::Kmeans::new(num_kernels, &candidates, euclidean_distance, image_mean).move_means()
+    kmeans::Kmeans::new(num_kernels, &candidates, euclidean_distance, image_mean, rng).move_means()
 }
 
 pub fn project_all_through(images: &Vec<Image>, kernels: &Vec<Image>) -> Vec<Image> {
@@ -42,12 +56,13 @@ pub fn project_image_through(img: &Image, kernels: &Vec<Image>) -> Vec<Image> {
 }
 
 pub fn apply_kernel_to(img: &Image, kernel: &Image) -> Image {
-    assert_eq!(kernel.side(), KERNEL_SIZE);
-    let mut result = Image::new();
-    for (x, y) in img.x_y_step_iter(STRIDE) {
+    assert_eq!(kernel.width(), KERNEL_SIZE);
+    assert_eq!(kernel.height(), KERNEL_SIZE);
+    let mut result = Image::with_width(img.width().div_ceil(STRIDE));
+    for (x, y) in img.plane_step_iter(STRIDE) {
         result.add(pixelize(euclidean_distance(&img.subimage(x, y, KERNEL_SIZE), kernel)));
     }
-    result
+    result.with_planes(img.planes())
 }
 
 pub fn pixelize(distance: R64) -> u8 {
@@ -64,11 +79,13 @@ fn add_kernels_from_to(img: &Image, raw_filters: &mut Vec<Image>, kernel_size: u
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
 
     #[test]
     fn test_kernels() {
         let img = Image::from_vec(&(1..10).collect());
-        let filters = extract_kernels_from(&vec![img], 4, 2);
+        let filters = extract_kernels_from(&vec![img], 4, 2, &mut StdRng::seed_from_u64(1));
         let filter_means: Vec<u8> = filters.iter().map(|f| f.pixel_mean()).collect();
 
         let target_means_1: Vec<u8> = vec![3, 0, 6, 1];
@@ -77,6 +94,26 @@ mod tests {
                 test_filter_means(&target_means_2, &filter_means));
     }
 
+    #[test]
+    fn test_rectangular_kernel() {
+        let img = Image::from_pixels(5, 3, (1..16).collect());
+        let kernel = Image::from_pixels(KERNEL_SIZE, KERNEL_SIZE, vec![0; KERNEL_SIZE * KERNEL_SIZE]);
+        let projected = apply_kernel_to(&img, &kernel);
+        assert_eq!(3, projected.width());
+        assert_eq!(2, projected.height());
+    }
+
+    #[test]
+    fn test_stacked_kernel() {
+        let planes: Vec<Image> = (0..3).map(|p| Image::from_pixels(3, 3, vec![p * 100; 9])).collect();
+        let kernel = Image::from_pixels(KERNEL_SIZE, KERNEL_SIZE, vec![0; KERNEL_SIZE * KERNEL_SIZE]);
+        let projected = apply_kernel_to(&Image::stacked(&planes), &kernel);
+        assert_eq!(3, projected.planes());
+        assert_eq!(6, projected.height());
+        let separate: Vec<Image> = planes.iter().map(|plane| apply_kernel_to(plane, &kernel)).collect();
+        assert_eq!(Image::stacked(&separate), projected);
+    }
+
     fn test_filter_means(target_means: &Vec<u8>, filter_means: &Vec<u8>) -> bool {
         for mean in filter_means.iter() {
             if !target_means.contains(mean) && !target_means.contains(&(mean - 1)) && !target_means.contains(&(mean + 1)) {
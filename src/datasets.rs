@@ -0,0 +1,334 @@
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use crate::mnist_data::{Image, Grid, init_from_files};
+use crate::idx::invalid_data;
+use crate::cifar::{ColorMode, read_cifar_file};
+use crate::netpbm;
+use crate::options::{Args, OptionSpec};
+
+pub const DATA_DIR_VAR: &str = "FLAIRS33_DATA_DIR";
+
+pub const DATASET: &str = "--dataset";
+pub const DATA_DIR: &str = "--data-dir";
+pub const TRAIN_IMAGES: &str = "--train-images";
+pub const TRAIN_LABELS: &str = "--train-labels";
+pub const TEST_IMAGES: &str = "--test-images";
+pub const TEST_LABELS: &str = "--test-labels";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Dataset {
+    Mnist,
+    FashionMnist,
+    Kmnist,
+    Emnist(EmnistSplit),
+    Cifar10(ColorMode),
+    Folders(ColorMode),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EmnistSplit {
+    ByClass,
+    ByMerge,
+    Balanced,
+    Letters,
+    Digits,
+    Mnist,
+}
+
+pub const ALL_DATASETS: [Dataset; 13] = [Dataset::Mnist, Dataset::FashionMnist, Dataset::Kmnist,
+    Dataset::Emnist(EmnistSplit::ByClass), Dataset::Emnist(EmnistSplit::ByMerge),
+    Dataset::Emnist(EmnistSplit::Balanced), Dataset::Emnist(EmnistSplit::Letters),
+    Dataset::Emnist(EmnistSplit::Digits), Dataset::Emnist(EmnistSplit::Mnist),
+    Dataset::Cifar10(ColorMode::Grayscale), Dataset::Cifar10(ColorMode::Channels),
+    Dataset::Folders(ColorMode::Grayscale), Dataset::Folders(ColorMode::Channels)];
+
+const CIFAR_TRAINING_BATCHES: usize = 5;
+
+impl EmnistSplit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmnistSplit::ByClass => "byclass",
+            EmnistSplit::ByMerge => "bymerge",
+            EmnistSplit::Balanced => "balanced",
+            EmnistSplit::Letters => "letters",
+            EmnistSplit::Digits => "digits",
+            EmnistSplit::Mnist => "mnist",
+        }
+    }
+}
+
+impl Dataset {
+    pub fn from_name(name: &str) -> Option<Dataset> {
+        ALL_DATASETS.iter().find(|d| d.to_string() == name).copied()
+    }
+
+    /// EMNIST letters are labeled 1 through 26; every other split starts at 0.
+    pub fn label_range(&self) -> (u8, u8) {
+        match self {
+            Dataset::Mnist | Dataset::FashionMnist | Dataset::Kmnist | Dataset::Cifar10(_) => (0, 9),
+            Dataset::Emnist(split) => match split {
+                EmnistSplit::ByClass => (0, 61),
+                EmnistSplit::ByMerge | EmnistSplit::Balanced => (0, 46),
+                EmnistSplit::Letters => (1, 26),
+                EmnistSplit::Digits | EmnistSplit::Mnist => (0, 9),
+            },
+            Dataset::Folders(_) => (0, u8::MAX),
+        }
+    }
+
+    /// EMNIST images are stored column-major, so they must be transposed to be upright.
+    pub fn transposed(&self) -> bool {
+        matches!(self, Dataset::Emnist(_))
+    }
+
+    pub fn is_idx(&self) -> bool {
+        !matches!(self, Dataset::Cifar10(_) | Dataset::Folders(_))
+    }
+
+    /// IDX datasets have an image file followed by a label file; CIFAR-10 has one or more batch files.
+    /// Folder datasets list the split's own directory first, followed by the other split's
+    /// directory, so that both splits assign labels from the same set of class names.
+    pub fn file_names(&self, training: bool) -> Vec<String> {
+        match self {
+            Dataset::Folders(_) => if training {
+                vec!["train".to_string(), "test".to_string()]
+            } else {
+                vec!["test".to_string(), "train".to_string()]
+            },
+            Dataset::Cifar10(_) => if training {
+                (1..=CIFAR_TRAINING_BATCHES).map(|i| format!("data_batch_{}.bin", i)).collect()
+            } else {
+                vec!["test_batch.bin".to_string()]
+            },
+            Dataset::Emnist(split) => ["images-idx3-ubyte", "labels-idx1-ubyte"].iter()
+                .map(|contents| format!("emnist-{}-{}-{}", split.name(), if training {"train"} else {"test"}, contents))
+                .collect(),
+            _ => ["images-idx3-ubyte", "labels-idx1-ubyte"].iter()
+                .map(|contents| format!("{}-{}", if training {"train"} else {"t10k"}, contents))
+                .collect()
+        }
+    }
+
+    /// The files that the images loaded from `file_names` depend on, for telling when they
+    /// change. A folder dataset depends on its directories and every image file in them.
+    pub fn source_files(&self, file_names: &[String]) -> io::Result<Vec<String>> {
+        match self {
+            Dataset::Folders(_) => {
+                let mut files = Vec::new();
+                for dir in file_names {
+                    files.push(dir.clone());
+                    files.extend(netpbm::image_files(dir)?.into_iter().map(|(_, file_name)| file_name));
+                }
+                Ok(files)
+            }
+            _ => Ok(file_names.to_vec())
+        }
+    }
+
+    pub fn load(&self, file_names: &[String]) -> io::Result<Vec<(u8,Image)>> {
+        match self {
+            Dataset::Cifar10(mode) => {
+                let mut images = Vec::new();
+                for file_name in file_names {
+                    images.append(&mut read_cifar_file(file_name, *mode)?);
+                }
+                Ok(images)
+            }
+            Dataset::Folders(mode) => netpbm::load_labeled_folders(&file_names[0], &netpbm::class_names(file_names)?, *mode),
+            _ => self.load_idx(&file_names[0], &file_names[1])
+        }
+    }
+
+    fn load_idx(&self, image_file_name: &str, label_file_name: &str) -> io::Result<Vec<(u8,Image)>> {
+        let mut images = init_from_files(image_file_name, label_file_name)?;
+        let (min_label, max_label) = self.label_range();
+        if let Some((label, _)) = images.iter().find(|(label, _)| *label < min_label || *label > max_label) {
+            return Err(invalid_data(format!("{}: label {} is outside the {}..={} range of {}",
+                                            label_file_name, label, min_label, max_label, self)));
+        }
+        if self.transposed() {
+            images = images.iter().map(|(label, img)| (*label, img.transposed())).collect();
+        }
+        Ok(images)
+    }
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dataset::Mnist => write!(f, "mnist"),
+            Dataset::FashionMnist => write!(f, "fashion"),
+            Dataset::Kmnist => write!(f, "kmnist"),
+            Dataset::Emnist(split) => write!(f, "emnist-{}", split.name()),
+            Dataset::Cifar10(ColorMode::Grayscale) => write!(f, "cifar10"),
+            Dataset::Cifar10(ColorMode::Channels) => write!(f, "cifar10-channels"),
+            Dataset::Folders(ColorMode::Grayscale) => write!(f, "folders"),
+            Dataset::Folders(ColorMode::Channels) => write!(f, "folders-channels"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataFiles {
+    pub dataset: Dataset,
+    pub training: Vec<String>,
+    pub testing: Vec<String>,
+}
+
+impl DataFiles {
+    pub fn in_directory(dataset: Dataset, dir: &str) -> DataFiles {
+        DataFiles {
+            dataset,
+            training: dataset.file_names(true).iter().map(|f| in_dir(dir, f)).collect(),
+            testing: dataset.file_names(false).iter().map(|f| in_dir(dir, f)).collect(),
+        }
+    }
+
+    /// The data directory comes from `--data-dir`, then the `FLAIRS33_DATA_DIR` environment
+    /// variable, then the current directory. Any individual IDX file may be overridden by its own option.
+    pub fn from_args(args: &Args) -> io::Result<DataFiles> {
+        let dataset = match args.value(DATASET) {
+            None => Dataset::Mnist,
+            Some(name) => Dataset::from_name(&name).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+                format!("unknown dataset \"{}\"; choose one of {}", name, dataset_names().join(", "))))?
+        };
+        let dir = args.value(DATA_DIR)
+            .or_else(|| env::var(DATA_DIR_VAR).ok())
+            .unwrap_or_else(|| ".".to_string());
+        let mut files = DataFiles::in_directory(dataset, &dir);
+        for (option, training, i) in [(TRAIN_IMAGES, true, 0), (TRAIN_LABELS, true, 1),
+                                      (TEST_IMAGES, false, 0), (TEST_LABELS, false, 1)].iter() {
+            if let Some(value) = args.value(option) {
+                if !dataset.is_idx() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} only applies to IDX datasets", option)));
+                }
+                let list = if *training {&mut files.training} else {&mut files.testing};
+                list[*i] = value;
+            }
+        }
+        Ok(files)
+    }
+
+    pub fn all_files(&self) -> Vec<&str> {
+        self.training.iter().chain(self.testing.iter()).map(|f| f.as_str()).collect()
+    }
+
+    pub fn check_present(&self) -> io::Result<()> {
+        let missing: Vec<&str> = self.all_files().into_iter()
+            .filter(|f| !Path::new(f).exists())
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotFound,
+                               format!("missing data files:\n\t{}\nUse {} or the {} environment variable to locate them.",
+                                       missing.join("\n\t"), DATA_DIR, DATA_DIR_VAR)))
+        }
+    }
+
+    /// Both splits need images of the same width, height and planes, since every distance
+    /// compares images pixel by pixel. Each loader already checks the images of its own split.
+    pub fn check_loaded(&self, training_images: &[(u8,Image)], testing_images: &[(u8,Image)]) -> io::Result<()> {
+        let (training, testing) = match (training_images.first(), testing_images.first()) {
+            (Some((_, training)), Some((_, testing))) => (training, testing),
+            (None, _) => return Err(invalid_data(format!("{}: no training images", self.training[0]))),
+            (_, None) => return Err(invalid_data(format!("{}: no testing images", self.testing[0])))
+        };
+        if !testing.same_size(training) || testing.planes() != training.planes() {
+            return Err(invalid_data(format!("{}: the {}x{} testing images with {} planes differ from the {}x{} training images with {} planes",
+                                            self.testing[0], testing.width(), testing.height(), testing.planes(),
+                                            training.width(), training.height(), training.planes())));
+        }
+        Ok(())
+    }
+}
+
+pub fn dataset_names() -> Vec<String> {
+    ALL_DATASETS.iter().map(|d| d.to_string()).collect()
+}
+
+/// The options read by `DataFiles::from_args`.
+pub fn option_specs() -> Vec<OptionSpec> {
+    vec![
+        OptionSpec::valued(DATASET, "name", format!("one of {} (default: mnist)", dataset_names().join(", "))),
+        OptionSpec::valued(DATA_DIR, "dir", format!("directory containing the dataset files (default: ${} or the current directory)", DATA_DIR_VAR)),
+        OptionSpec::valued(TRAIN_IMAGES, "file", "training images, overriding the data directory (IDX datasets only)".to_string()),
+        OptionSpec::valued(TRAIN_LABELS, "file", "training labels, overriding the data directory (IDX datasets only)".to_string()),
+        OptionSpec::valued(TEST_IMAGES, "file", "testing images, overriding the data directory (IDX datasets only)".to_string()),
+        OptionSpec::valued(TEST_LABELS, "file", "testing labels, overriding the data directory (IDX datasets only)".to_string()),
+    ]
+}
+
+fn in_dir(dir: &str, file_name: &str) -> String {
+    Path::new(dir).join(file_name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Section;
+
+    fn to_args(args: &[&str]) -> Args {
+        let sections = [Section {title: "Data options", notes: vec![], options: option_specs()}];
+        Args::parse(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>(), &sections).unwrap()
+    }
+
+    #[test]
+    fn test_from_args() {
+        let files = DataFiles::from_args(&to_args(&[DATA_DIR, "/data/mnist", TEST_LABELS, "labels"])).unwrap();
+        assert_eq!(Dataset::Mnist, files.dataset);
+        assert_eq!("/data/mnist/train-images-idx3-ubyte", files.training[0]);
+        assert_eq!("/data/mnist/t10k-images-idx3-ubyte", files.testing[0]);
+        assert_eq!("labels", files.testing[1]);
+    }
+
+    #[test]
+    fn test_datasets() {
+        for dataset in ALL_DATASETS.iter() {
+            assert_eq!(Some(*dataset), Dataset::from_name(&dataset.to_string()));
+        }
+        assert_eq!(None, Dataset::from_name("cifar100"));
+
+        let files = DataFiles::from_args(&to_args(&[DATASET, "emnist-letters", DATA_DIR, "emnist"])).unwrap();
+        assert_eq!(Dataset::Emnist(EmnistSplit::Letters), files.dataset);
+        assert_eq!(in_dir("emnist", "emnist-letters-train-images-idx3-ubyte"), files.training[0]);
+        assert_eq!(in_dir("emnist", "emnist-letters-test-labels-idx1-ubyte"), files.testing[1]);
+        assert!(DataFiles::from_args(&to_args(&[DATASET, "nope"])).is_err());
+
+        let files = DataFiles::from_args(&to_args(&[DATASET, "fashion"])).unwrap();
+        assert!(files.training[1].ends_with("train-labels-idx1-ubyte"));
+
+        let files = DataFiles::from_args(&to_args(&[DATASET, "cifar10-channels", DATA_DIR, "cifar"])).unwrap();
+        assert_eq!(Dataset::Cifar10(ColorMode::Channels), files.dataset);
+        assert_eq!(5, files.training.len());
+        assert_eq!(vec![in_dir("cifar", "test_batch.bin")], files.testing);
+        assert!(DataFiles::from_args(&to_args(&[DATASET, "cifar10", TRAIN_IMAGES, "x"])).is_err());
+
+        let files = DataFiles::from_args(&to_args(&[DATASET, "folders", DATA_DIR, "scans"])).unwrap();
+        assert_eq!(vec![in_dir("scans", "train"), in_dir("scans", "test")], files.training);
+        assert_eq!(vec![in_dir("scans", "test"), in_dir("scans", "train")], files.testing);
+    }
+
+    #[test]
+    fn test_check_loaded() {
+        let files = DataFiles::in_directory(Dataset::Folders(ColorMode::Grayscale), "scans");
+        let square = vec![(0, Image::from_pixels(2, 2, vec![0; 4]))];
+        assert!(files.check_loaded(&square, &square).is_ok());
+        assert!(files.check_loaded(&[], &square).is_err());
+        assert!(files.check_loaded(&square, &[]).is_err());
+        assert!(files.check_loaded(&square, &[(0, Image::from_pixels(4, 1, vec![0; 4]))]).is_err());
+        assert!(files.check_loaded(&square, &[(0, Image::from_pixels(2, 2, vec![0; 4]).with_planes(2))]).is_err());
+    }
+
+    #[test]
+    fn test_missing() {
+        let files = DataFiles::in_directory(Dataset::Mnist, "no_such_directory");
+        let err = files.check_present().unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+        for file in files.all_files() {
+            assert!(err.to_string().contains(file));
+        }
+    }
+}
@@ -2,13 +2,53 @@ use crate::mnist_data::{Image, Grid};
 use decorum::R64;
 
 pub fn euclidean_distance(img1: &Image, img2: &Image) -> R64 {
-    assert_eq!(img1.side(), img2.side());
+    assert!(img1.same_size(img2));
     assert_eq!(img1.len(), img2.len());
     R64::from_inner(img1.x_y_iter()
         .map(|(x, y)| (img1.get(x, y) as f64 - img2.get(x, y) as f64).powf(2.0))
         .sum())
 }
 
+/// `euclidean_distance`, unless it exceeds `bound`. It stops adding up the squared
+/// differences as soon as it does, and returns `None`.
+pub fn euclidean_distance_within(img1: &Image, img2: &Image, bound: R64) -> Option<R64> {
+    squared_differences_within(img1, img2, 0.0, bound.into_inner()).map(R64::from_inner)
+}
+
+/// The sum of the squared differences of `img1` and `img2`, unless `preceding` plus the
+/// partial sum exceeds `bound`. Each partial sum is computed exactly as in `euclidean_distance`.
+pub fn squared_differences_within(img1: &Image, img2: &Image, preceding: f64, bound: f64) -> Option<f64> {
+    assert!(img1.same_size(img2));
+    let mut sum = 0.0;
+    for (x, y) in img1.x_y_iter() {
+        sum += (img1.get(x, y) as f64 - img2.get(x, y) as f64).powf(2.0);
+        if preceding + sum > bound {
+            return None;
+        }
+    }
+    Some(sum)
+}
+
+pub fn manhattan_distance(img1: &Image, img2: &Image) -> u32 {
+    assert!(img1.same_size(img2));
+    img1.x_y_iter()
+        .map(|(x, y)| (img1.get(x, y) as i32 - img2.get(x, y) as i32).unsigned_abs())
+        .sum()
+}
+
+/// `manhattan_distance`, unless it exceeds `bound`, as in `euclidean_distance_within`.
+pub fn manhattan_distance_within(img1: &Image, img2: &Image, bound: u32) -> Option<u32> {
+    assert!(img1.same_size(img2));
+    let mut sum = 0;
+    for (x, y) in img1.x_y_iter() {
+        sum += (img1.get(x, y) as i32 - img2.get(x, y) as i32).unsigned_abs();
+        if sum > bound {
+            return None;
+        }
+    }
+    Some(sum)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -18,5 +58,17 @@ mod tests {
         let img1 = Image::from_vec(&vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
         let img2 = Image::from_vec(&vec![9, 8, 7, 6, 5, 4, 3, 2, 1]);
         assert_eq!(2.0 * (64.0 + 36.0 + 16.0 + 4.0), euclidean_distance(&img1, &img2).into_inner());
+        assert_eq!(2 * (8 + 6 + 4 + 2), manhattan_distance(&img1, &img2));
+    }
+
+    #[test]
+    fn test_within() {
+        let img1 = Image::from_vec(&vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let img2 = Image::from_vec(&vec![9, 8, 7, 6, 5, 4, 3, 2, 1]);
+        let distance = euclidean_distance(&img1, &img2);
+        assert_eq!(Some(distance), euclidean_distance_within(&img1, &img2, distance));
+        assert_eq!(None, euclidean_distance_within(&img1, &img2, R64::from_inner(distance.into_inner() - 1.0)));
+        assert_eq!(Some(40), manhattan_distance_within(&img1, &img2, 40));
+        assert_eq!(None, manhattan_distance_within(&img1, &img2, 39));
     }
 }
\ No newline at end of file
@@ -3,6 +3,7 @@ use std::collections::HashSet;
 use std::hash::Hash;
 use std::fmt;
 
+#[derive(Clone)]
 pub struct HashHistogram<K: Hash + Eq + Copy> {
     map: HashMap<K,usize>
 }
@@ -15,7 +16,11 @@ impl<K: Hash + Eq + Copy> HashHistogram<K> {
     }
 
     pub fn bump(&mut self, key: K) {
-        let value: usize = self.map.get(&key).unwrap_or(&0) + 1;
+        self.bump_by(key, 1);
+    }
+
+    pub fn bump_by(&mut self, key: K, count: usize) {
+        let value: usize = self.map.get(&key).unwrap_or(&0) + count;
         self.map.insert(key, value);
     }
 
@@ -25,12 +30,6 @@ impl<K: Hash + Eq + Copy> HashHistogram<K> {
             .collect()
     }
 
-    pub fn mode(&self) -> K {
-        *(self.map.iter()
-            .max_by_key(|entry| entry.1)
-            .unwrap().0)
-    }
-
     pub fn total_count(&self) -> usize {
         self.map.iter().map(|entry| entry.1).sum()
     }
@@ -74,7 +73,6 @@ mod tests {
         assert_eq!(zeros, hist.get(0));
         assert_eq!(ones, hist.get(1));
         assert_eq!(twos, hist.get(2));
-        assert_eq!(2, hist.mode());
         assert_eq!(zeros + ones + twos, hist.total_count());
     }
 }
\ No newline at end of file
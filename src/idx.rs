@@ -0,0 +1,105 @@
+use std::fs;
+use std::io;
+
+const UNSIGNED_BYTE: u8 = 0x08;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdxData {
+    dimensions: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl IdxData {
+    pub fn dimensions(&self) -> &[usize] {
+        &self.dimensions
+    }
+
+    pub fn item_size(&self) -> usize {
+        self.dimensions[1..].iter().product()
+    }
+
+    pub fn items(&self) -> std::slice::Chunks<'_, u8> {
+        self.data.chunks(self.item_size().max(1))
+    }
+
+    pub fn move_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub fn read_idx_file(file_name: &str) -> io::Result<IdxData> {
+    let bytes = fs::read(file_name)?;
+    parse_idx(&bytes).map_err(|e| invalid_data(format!("{}: {}", file_name, e)))
+}
+
+pub fn parse_idx(bytes: &[u8]) -> io::Result<IdxData> {
+    if bytes.len() < 4 {
+        return Err(invalid_data(format!("file has {} bytes; too short for an IDX magic number", bytes.len())));
+    }
+    if bytes[0] != 0 || bytes[1] != 0 {
+        return Err(invalid_data(format!("bad IDX magic number {:02x}{:02x}{:02x}{:02x}", bytes[0], bytes[1], bytes[2], bytes[3])));
+    }
+    if bytes[2] != UNSIGNED_BYTE {
+        return Err(invalid_data(format!("unsupported IDX element type 0x{:02x}; only unsigned bytes (0x08) are supported", bytes[2])));
+    }
+    let num_dimensions = bytes[3] as usize;
+    if num_dimensions == 0 {
+        return Err(invalid_data("IDX header declares zero dimensions".to_string()));
+    }
+
+    let header_size = 4 + 4 * num_dimensions;
+    if bytes.len() < header_size {
+        return Err(invalid_data(format!("header declares {} dimensions but file has only {} bytes", num_dimensions, bytes.len())));
+    }
+    let dimensions: Vec<usize> = bytes[4..header_size].chunks(4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        .collect();
+
+    let expected = dimensions.iter().try_fold(1usize, |product, d| product.checked_mul(*d))
+        .ok_or_else(|| invalid_data(format!("dimensions {:?} are too large", dimensions)))?;
+    let actual = bytes.len() - header_size;
+    if expected != actual {
+        return Err(invalid_data(format!("dimensions {:?} require {} data bytes, but file contains {}", dimensions, expected, actual)));
+    }
+    Ok(IdxData {dimensions, data: bytes[header_size..].to_vec()})
+}
+
+pub fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+pub fn encode_idx(dimensions: &[usize], data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0, 0, UNSIGNED_BYTE, dimensions.len() as u8];
+    for d in dimensions {
+        bytes.extend_from_slice(&(*d as u32).to_be_bytes());
+    }
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let data: Vec<u8> = (0..12).collect();
+        let idx = parse_idx(&encode_idx(&[2, 3, 2], &data)).unwrap();
+        assert_eq!(&[2, 3, 2], idx.dimensions());
+        assert_eq!(6, idx.item_size());
+        let items: Vec<&[u8]> = idx.items().collect();
+        assert_eq!(vec![&data[0..6], &data[6..12]], items);
+    }
+
+    #[test]
+    fn test_bad_headers() {
+        assert!(parse_idx(&[0, 0, 8]).is_err());
+        assert!(parse_idx(&[1, 0, 8, 1, 0, 0, 0, 0]).is_err());
+        assert!(parse_idx(&[0, 0, 0x0D, 1, 0, 0, 0, 0]).is_err());
+        assert!(parse_idx(&[0, 0, 8, 2, 0, 0, 0, 1]).is_err());
+        assert!(parse_idx(&encode_idx(&[3], &[1, 2])).is_err());
+        assert!(parse_idx(&encode_idx(&[3], &[1, 2, 3, 4])).is_err());
+        assert!(parse_idx(&encode_idx(&[u32::MAX as usize, u32::MAX as usize, u32::MAX as usize], &[])).is_err());
+    }
+}
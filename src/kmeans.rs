@@ -1,5 +1,5 @@
 use decorum::R64;
-use rand::thread_rng;
+use rand::Rng;
 use rand::distributions::{Distribution, Uniform, WeightedIndex};
 
 #[allow(unused_imports)]
@@ -12,8 +12,8 @@ pub struct Kmeans<T, V: Copy + Eq + Ord, D: Fn(&T,&T) -> V> {
 }
 
 impl <T: Clone + Eq, V: Copy + Eq + Ord + Into<f64>, D: Fn(&T,&T) -> V> Kmeans<T,V,D> {
-    pub fn new<M: Fn(&Vec<T>) -> T>(k: usize, data: &[T], distance: D, mean: M) -> Kmeans<T,V,D> {
-        Kmeans {means: kmeans_iterate(k, data, &distance, &mean), distance}
+    pub fn new<M: Fn(&Vec<T>) -> T, R: Rng>(k: usize, data: &[T], distance: D, mean: M, rng: &mut R) -> Kmeans<T,V,D> {
+        Kmeans {means: kmeans_iterate(k, data, &distance, &mean, rng), distance}
     }
 
     #[cfg(test)]
@@ -30,23 +30,22 @@ impl <T: Clone + Eq, V: Copy + Eq + Ord + Into<f64>, D: Fn(&T,&T) -> V> Kmeans<T
     pub fn move_means(self) -> Vec<T> {self.means}
 }
 
-fn initial_plus_plus<T: Clone + Eq, V: Copy + Eq + Ord + Into<f64>, D: Fn(&T,&T) -> V>(k: usize, distance: &D, data: &[T]) -> Vec<T> {
+fn initial_plus_plus<T: Clone + Eq, V: Copy + Eq + Ord + Into<f64>, D: Fn(&T,&T) -> V, R: Rng>(k: usize, distance: &D, data: &[T], rng: &mut R) -> Vec<T> {
     let mut result = Vec::new();
-    let mut rng = thread_rng();
     let range = Uniform::new(0, data.len());
-    result.push(data[range.sample(&mut rng)].clone());
+    result.push(data[range.sample(rng)].clone());
     while result.len() < k {
         let squared_distances: Vec<f64> = data.iter()
             .map(|datum| 1.0 + distance(datum, result.last().unwrap()).into().powf(2.0))
             .collect();
         let dist = WeightedIndex::new(&squared_distances).unwrap();
-        result.push(data[dist.sample(&mut rng)].clone());
+        result.push(data[dist.sample(rng)].clone());
     }
     result
 }
 
-fn kmeans_iterate<T: Clone + Eq, V: Copy + Eq + Ord + Into<f64>, D: Fn(&T,&T) -> V, M: Fn(&Vec<T>) -> T>(k: usize, data: &[T], distance: &D, mean: &M) -> Vec<T> {
-    let mut result = initial_plus_plus(k, distance, data);
+fn kmeans_iterate<T: Clone + Eq, V: Copy + Eq + Ord + Into<f64>, D: Fn(&T,&T) -> V, M: Fn(&Vec<T>) -> T, R: Rng>(k: usize, data: &[T], distance: &D, mean: &M, rng: &mut R) -> Vec<T> {
+    let mut result = initial_plus_plus(k, distance, data, rng);
     loop {
         let mut classifications: Vec<Vec<T>> = (0..k).map(|_| Vec::new()).collect();
         for datum in data {
@@ -79,6 +78,8 @@ fn classify<T: Clone + Eq, V: Copy + Eq + Ord + Into<f64>, D: Fn(&T,&T) -> V>(ta
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
 
     fn manhattan(n1: &i32, n2: &i32) -> R64 {
         let mut diff = n1 - n2;
@@ -96,7 +97,7 @@ mod tests {
         let target_means = vec![3, 11, 25, 40];
         let data = vec![2, 3, 4, 10, 11, 12, 24, 25, 26, 35, 40, 45];
         let kmeans =
-            Kmeans::new(target_means.len(), &data, manhattan, mean);
+            Kmeans::new(target_means.len(), &data, manhattan, mean, &mut StdRng::seed_from_u64(1));
         let mut sorted_means = kmeans.copy_means();
         sorted_means.sort();
         let unsorted_means = kmeans.copy_means();
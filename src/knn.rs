@@ -1,16 +1,166 @@
 extern crate decorum;
-use crate::training_harness::Classifier;
-use crate::hash_histogram::HashHistogram;
+use crate::training_harness::{Classifier, ConfusionMatrix, margin};
+use crate::parallel::map_with_progress;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+/// How much each of the k nearest neighbors counts in the vote, given its distance d as
+/// converted by the model's `true_distance`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Weighting {
+    /// One vote each.
+    Uniform,
+    /// 1/d. Neighbors at distance 0, if any, are the only ones to vote.
+    InverseDistance,
+    /// 1/d², with neighbors at distance 0 as in `InverseDistance`.
+    InverseSquared,
+    /// exp(-d²/2σ²), where σ is the distance of the k-th nearest neighbor.
+    Gaussian,
+    /// Dudani's (d_k - d)/(d_k - d_1): 1 for the nearest neighbor down to 0 for the k-th.
+    Dudani,
+}
+
+impl Weighting {
+    pub fn from_name(name: &str) -> Option<Weighting> {
+        match name {
+            "uniform" => Some(Weighting::Uniform),
+            "inverse" => Some(Weighting::InverseDistance),
+            "inverse-squared" => Some(Weighting::InverseSquared),
+            "gaussian" => Some(Weighting::Gaussian),
+            "dudani" => Some(Weighting::Dudani),
+            _ => None
+        }
+    }
+
+    /// The weight of a neighbor at distance `d` when it is the same for every k, given
+    /// the distance of the nearest neighbor. The other weightings give 1.
+    fn fixed_weight(&self, d: f64, nearest: f64) -> f64 {
+        match self {
+            Weighting::InverseDistance | Weighting::InverseSquared if nearest == 0.0 => if d == 0.0 {1.0} else {0.0},
+            Weighting::InverseDistance => 1.0 / d,
+            Weighting::InverseSquared => 1.0 / (d * d),
+            _ => 1.0
+        }
+    }
+
+    /// The total weight of the neighbors in `tally` among `neighbors`, the first k
+    /// neighbors nearest first.
+    fn score(&self, tally: &Tally, neighbors: &[(f64,u8)]) -> f64 {
+        let nearest = neighbors[0].0;
+        let farthest = neighbors[neighbors.len() - 1].0;
+        match self {
+            Weighting::Uniform => tally.count as f64,
+            Weighting::InverseDistance | Weighting::InverseSquared => tally.total_weight,
+            Weighting::Gaussian if farthest == 0.0 => tally.count as f64,
+            Weighting::Gaussian => neighbors.iter()
+                .filter(|(_, label)| *label == tally.label)
+                .map(|(d, _)| (-(d / farthest).powi(2) / 2.0).exp())
+                .sum(),
+            Weighting::Dudani if farthest == nearest => tally.count as f64,
+            Weighting::Dudani => (tally.count as f64 * farthest - tally.total_distance) / (farthest - nearest)
+        }
+    }
+}
+
+impl fmt::Display for Weighting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Weighting::Uniform => write!(f, "uniform"),
+            Weighting::InverseDistance => write!(f, "inverse"),
+            Weighting::InverseSquared => write!(f, "inverse-squared"),
+            Weighting::Gaussian => write!(f, "gaussian"),
+            Weighting::Dudani => write!(f, "dudani"),
+        }
+    }
+}
+
+/// How a vote is decided when several labels have the highest total weight.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TieBreak {
+    /// The tied label of the nearest neighbor.
+    Nearest,
+    /// The tied label whose neighbors have the smallest total distance, then the nearest.
+    TotalDistance,
+    /// The lowest tied label.
+    LowestLabel,
+    /// Leave out the farthest neighbor and vote again, until the vote is untied.
+    ReduceK,
+}
+
+impl TieBreak {
+    pub fn from_name(name: &str) -> Option<TieBreak> {
+        match name {
+            "nearest" => Some(TieBreak::Nearest),
+            "distance" => Some(TieBreak::TotalDistance),
+            "label" => Some(TieBreak::LowestLabel),
+            "reduce-k" => Some(TieBreak::ReduceK),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for TieBreak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TieBreak::Nearest => write!(f, "nearest"),
+            TieBreak::TotalDistance => write!(f, "distance"),
+            TieBreak::LowestLabel => write!(f, "label"),
+            TieBreak::ReduceK => write!(f, "reduce-k"),
+        }
+    }
+}
+
+/// A distance function that gives up, returning `None`, once the distance exceeds the
+/// bound it is given.
+pub type BoundedDistance<I, M> = fn(&I, &I, M) -> Option<M>;
 
 pub struct Knn<I, M, D: Fn(&I,&I) -> M> {
     k: usize,
     images: Vec<(u8,I)>,
     distance: D,
+    distance_within: Option<BoundedDistance<I,M>>,
+    true_distance: fn(f64) -> f64,
+    weighting: Weighting,
+    tie_break: TieBreak,
+    threads: usize,
 }
 
 impl<I, M, D: Fn(&I,&I) -> M> Knn<I, M, D> {
     pub fn new(k: usize, distance: D) -> Knn<I, M, D> {
-        Knn {k, images: Vec::new(), distance}
+        Knn {k, images: Vec::new(), distance, distance_within: None, true_distance: |d| d, weighting: Weighting::Uniform, tie_break: TieBreak::Nearest, threads: 1}
+    }
+
+    pub fn with_weighting(mut self, weighting: Weighting) -> Knn<I, M, D> {
+        self.weighting = weighting;
+        self
+    }
+
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Knn<I, M, D> {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// Once k neighbors are found, `distance_within` abandons each remaining training
+    /// example as soon as it is farther away than the k-th nearest so far. It must give
+    /// the same distances as `distance` when it does not give up.
+    pub fn with_distance_within(mut self, distance_within: BoundedDistance<I,M>) -> Knn<I, M, D> {
+        self.distance_within = Some(distance_within);
+        self
+    }
+
+    /// `true_distance` converts the values of `distance` to the distances that weigh the
+    /// votes, such as the square root of a squared Euclidean distance. It must not change
+    /// their order.
+    pub fn with_true_distance(mut self, true_distance: fn(f64) -> f64) -> Knn<I, M, D> {
+        self.true_distance = true_distance;
+        self
+    }
+
+    /// Classifies testing images on `threads` threads. The results do not depend on the
+    /// number of threads.
+    pub fn with_threads(mut self, threads: usize) -> Knn<I, M, D> {
+        self.threads = threads;
+        self
     }
 
     pub fn add_example(&mut self, img: (u8, I)) {
@@ -18,7 +168,174 @@ impl<I, M, D: Fn(&I,&I) -> M> Knn<I, M, D> {
     }
 }
 
-impl<I: Clone, M: Copy + Eq + Ord, D: Fn(&I,&I) -> M> Classifier<I> for Knn<I, M, D> {
+impl<I: Sync, M: Copy + Eq + Ord + Into<f64>, D: Fn(&I,&I) -> M + Sync> Knn<I, M, D> {
+    /// The distances and labels of the `k` training examples nearest to `example`, nearest first.
+    pub fn nearest(&self, example: &I, k: usize) -> Vec<(M,u8)> {
+        self.nearest_except(example, k, None)
+    }
+
+    /// Leaves the training example at index `excluded` out of the search. The k nearest
+    /// so far are kept in a max-heap, so ties are broken by the smaller label, as in
+    /// sorting all of the distances.
+    fn nearest_except(&self, example: &I, k: usize, excluded: Option<usize>) -> Vec<(M,u8)> {
+        let mut nearest: BinaryHeap<(M,u8)> = BinaryHeap::with_capacity(k + 1);
+        for (_, (label, img)) in self.images.iter().enumerate().filter(|(i, _)| Some(*i) != excluded) {
+            if nearest.len() < k {
+                nearest.push(((self.distance)(example, img), *label));
+            } else if let Some(&(farthest, farthest_label)) = nearest.peek() {
+                let distance = match self.distance_within {
+                    Some(distance_within) => distance_within(example, img, farthest),
+                    None => Some((self.distance)(example, img))
+                };
+                if let Some(distance) = distance.filter(|d| (*d, *label) < (farthest, farthest_label)) {
+                    nearest.pop();
+                    nearest.push((distance, *label));
+                }
+            }
+        }
+        nearest.into_sorted_vec()
+    }
+
+    /// `neighbors` with their true distances, for voting.
+    fn true_distances(&self, neighbors: &[(M,u8)]) -> Vec<(f64,u8)> {
+        neighbors.iter().map(|(distance, label)| ((self.true_distance)((*distance).into()), *label)).collect()
+    }
+
+    /// The classification of `example` and its margin, as in `classify_with_margin`, for each
+    /// k from 1 to `k_max`, from a single search for its nearest neighbors. When there are
+    /// fewer than `k_max` training examples, the larger values of k vote with all of them.
+    pub fn classify_for_each_k(&self, example: &I, k_max: usize) -> Vec<(u8, f64)> {
+        votes_for_each_k(&self.true_distances(&self.nearest(example, k_max)), k_max, self.weighting, self.tie_break)
+    }
+
+    /// `classify_for_each_k` for each testing image, reporting progress as it goes.
+    pub fn classify_all_for_each_k(&self, testing_images: &[(u8,I)], k_max: usize) -> Vec<Vec<(u8, f64)>> {
+        map_with_progress(testing_images, self.threads, |(_, img)| self.classify_for_each_k(img, k_max))
+    }
+
+    /// Leave-one-out cross-validation: the classification of each training example, in
+    /// order, by its k nearest neighbors among the other training examples.
+    pub fn leave_one_out(&self) -> Vec<u8> {
+        let indices: Vec<usize> = (0..self.images.len()).collect();
+        map_with_progress(&indices, self.threads, |i| vote(&self.true_distances(&self.nearest_except(&self.images[*i].1, self.k, Some(*i))), self.weighting, self.tie_break))
+    }
+
+    /// `leave_one_out` with the margin of each classification, as in `classify_with_margin`.
+    pub fn leave_one_out_with_margins(&self) -> Vec<(u8, f64)> {
+        let indices: Vec<usize> = (0..self.images.len()).collect();
+        map_with_progress(&indices, self.threads, |i| self.vote_with_margin(&self.nearest_except(&self.images[*i].1, self.k, Some(*i))))
+    }
+
+    fn vote_with_margin(&self, neighbors: &[(M,u8)]) -> (u8, f64) {
+        let neighbors = self.true_distances(neighbors);
+        *votes_for_each_k(&neighbors, neighbors.len(), self.weighting, self.tie_break).last().unwrap()
+    }
+
+    /// `leave_one_out` for each k from 1 to `k_max`, as in `classify_for_each_k`.
+    pub fn leave_one_out_for_each_k(&self, k_max: usize) -> Vec<Vec<(u8, f64)>> {
+        let indices: Vec<usize> = (0..self.images.len()).collect();
+        map_with_progress(&indices, self.threads, |i| votes_for_each_k(&self.true_distances(&self.nearest_except(&self.images[*i].1, k_max, Some(*i))), k_max, self.weighting, self.tie_break))
+    }
+}
+
+/// The neighbors with one label among the first k, from which each weighting's score
+/// is updated as k grows.
+struct Tally {
+    label: u8,
+    count: usize,
+    total_distance: f64,
+    total_weight: f64,
+}
+
+/// Adds the next neighbor to `tallies`, which are in order of each label's nearest neighbor.
+fn add_neighbor(tallies: &mut Vec<Tally>, (distance, label): (f64,u8), weight: f64) {
+    let index = match tallies.iter().position(|tally| tally.label == label) {
+        Some(index) => index,
+        None => {
+            tallies.push(Tally {label, count: 0, total_distance: 0.0, total_weight: 0.0});
+            tallies.len() - 1
+        }
+    };
+    let tally = &mut tallies[index];
+    tally.count += 1;
+    tally.total_distance += distance;
+    tally.total_weight += weight;
+}
+
+/// The total weight of the neighbors with each label, in order of each label's nearest
+/// neighbor. `neighbors` are ordered nearest first.
+fn scores(neighbors: &[(f64,u8)], weighting: Weighting) -> Vec<(u8,f64)> {
+    let mut tallies = Vec::new();
+    for neighbor in neighbors.iter() {
+        add_neighbor(&mut tallies, *neighbor, weighting.fixed_weight(neighbor.0, neighbors[0].0));
+    }
+    tallies.iter().map(|tally| (tally.label, weighting.score(tally, neighbors))).collect()
+}
+
+/// `scores` as fractions of their total, from the highest to the lowest.
+fn fractions(mut scores: Vec<(u8,f64)>) -> Vec<(u8,f64)> {
+    let total: f64 = scores.iter().map(|(_, score)| *score).sum();
+    if total > 0.0 {
+        scores.iter_mut().for_each(|(_, score)| *score /= total);
+    }
+    scores.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    scores
+}
+
+/// The label with the highest total weight among `neighbors`, which are ordered nearest
+/// first. Panics if there are none, as in `votes_for_each_k`.
+fn vote(neighbors: &[(f64,u8)], weighting: Weighting, tie_break: TieBreak) -> u8 {
+    votes_for_each_k(neighbors, neighbors.len(), weighting, tie_break).last().unwrap().0
+}
+
+/// The vote of the first k `neighbors` for each k from 1 to `k_max`, with the margin of
+/// their scores. The tallies of each
+/// label are updated one neighbor at a time, so only the Gaussian weighting, whose
+/// weights all depend on the k-th distance, looks back at the earlier neighbors. There is
+/// no vote without neighbors, so an empty `neighbors` panics.
+fn votes_for_each_k(neighbors: &[(f64,u8)], k_max: usize, weighting: Weighting, tie_break: TieBreak) -> Vec<(u8, f64)> {
+    assert!(!neighbors.is_empty(), "cannot vote without neighbors: the model has no training examples to compare with");
+    let mut tallies: Vec<Tally> = Vec::new();
+    let mut result: Vec<(u8, f64)> = Vec::with_capacity(k_max.max(neighbors.len()));
+    for k in 1..=neighbors.len() {
+        let neighbor = neighbors[k - 1];
+        add_neighbor(&mut tallies, neighbor, weighting.fixed_weight(neighbor.0, neighbors[0].0));
+        let scores: Vec<f64> = tallies.iter().map(|tally| weighting.score(tally, &neighbors[..k])).collect();
+        let best = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let tied: Vec<&Tally> = tallies.iter().zip(scores.iter()).filter(|(_, score)| **score == best).map(|(tally, _)| tally).collect();
+        let winner = match tie_break {
+            _ if tied.len() == 1 => tied[0].label,
+            TieBreak::Nearest => tied[0].label,
+            TieBreak::TotalDistance => tied.iter().min_by(|a, b| a.total_distance.total_cmp(&b.total_distance)).unwrap().label,
+            TieBreak::LowestLabel => tied.iter().map(|tally| tally.label).min().unwrap(),
+            // A single neighbor is never tied, so there is a vote for k - 1.
+            TieBreak::ReduceK => result[k - 2].0
+        };
+        result.push((winner, margin(&fractions(tallies.iter().map(|tally| tally.label).zip(scores).collect()))));
+    }
+    while result.len() < k_max {
+        result.push(*result.last().unwrap());
+    }
+    result
+}
+
+/// The confusion matrix of each k from 1 to `k_max`, given the classifications of each
+/// testing image by `classify_for_each_k`. This costs no more than a single run with `k_max`.
+/// With `min_margin`, each k's classifications with smaller margins are rejected.
+pub fn confusion_for_each_k<I>(testing_images: &[(u8,I)], classifications: &[Vec<(u8, f64)>], k_max: usize, min_margin: Option<f64>) -> Vec<ConfusionMatrix> {
+    let mut result = vec![ConfusionMatrix::new(); k_max];
+    for ((img_label, _), by_k) in testing_images.iter().zip(classifications.iter()) {
+        for (matrix, (classification, margin)) in result.iter_mut().zip(by_k.iter()) {
+            match min_margin {
+                Some(min_margin) => matrix.record_or_reject(*img_label, *classification, *margin, min_margin),
+                None => matrix.record(*img_label, *classification)
+            }
+        }
+    }
+    result
+}
+
+impl<I: Clone + Sync, M: Copy + Eq + Ord + Into<f64>, D: Fn(&I,&I) -> M + Sync> Classifier<I> for Knn<I, M, D> {
     fn train(&mut self, training_images: &Vec<(u8,I)>) {
         for img in training_images {
             // TODO: Bug report: self.add_example(img.clone()); // Flagged as type error by IDE, but compiles fine.
@@ -27,18 +344,127 @@ impl<I: Clone, M: Copy + Eq + Ord, D: Fn(&I,&I) -> M> Classifier<I> for Knn<I, M
     }
 
     fn classify(&self, example: &I) -> u8 {
-        let mut distances: Vec<(M, u8)> = self.images.iter()
-            .map(|img| ((self.distance)(example, &img.1), img.0))
+        vote(&self.true_distances(&self.nearest(example, self.k)), self.weighting, self.tie_break)
+    }
+
+    /// The fraction of the votes for each label among the k nearest neighbors, weighted
+    /// by the weighting.
+    fn label_scores(&self, example: &I) -> Vec<(u8,f64)> {
+        fractions(scores(&self.true_distances(&self.nearest(example, self.k)), self.weighting))
+    }
+
+    fn classify_with_margin(&self, example: &I) -> (u8, f64) {
+        self.vote_with_margin(&self.nearest(example, self.k))
+    }
+
+    fn classify_all(&self, testing_images: &[(u8,I)]) -> Vec<u8> {
+        map_with_progress(testing_images, self.threads, |(_, img)| self.classify(img))
+    }
+
+    fn classify_all_with_margins(&self, testing_images: &[(u8,I)]) -> Vec<(u8, f64)> {
+        map_with_progress(testing_images, self.threads, |(_, img)| self.classify_with_margin(img))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::euclidean_distance::euclidean_distance;
+    use crate::mnist_data::Image;
+
+    fn distance(a: &i32, b: &i32) -> i32 {
+        (a - b).abs()
+    }
+
+    #[test]
+    fn test_each_k() {
+        let mut model = Knn::new(3, distance).with_threads(2);
+        model.train(&vec![(1, 10), (0, 0), (1, 11), (0, 1), (1, 12)]);
+        assert_eq!(vec![(0, 0), (1, 0), (10, 1)], model.nearest(&0, 3));
+        let by_k: Vec<u8> = model.classify_for_each_k(&0, 7).iter().map(|(label, _)| *label).collect();
+        assert_eq!(7, by_k.len());
+        assert_eq!((0, 0, 0), (by_k[0], by_k[1], by_k[2]));
+        assert_eq!((0, 1, 1, 1), (by_k[3], by_k[4], by_k[5], by_k[6]));
+        assert_eq!(model.classify_with_margin(&0), model.classify_for_each_k(&0, 3)[2]);
+        let mut model = Knn::new(3, distance).with_distance_within(|a, b, bound| Some(distance(a, b)).filter(|d| *d <= bound));
+        model.train(&vec![(1, 10), (0, 0), (1, 11), (0, 1), (1, 12)]);
+        assert_eq!(vec![(0, 0), (1, 0), (10, 1)], model.nearest(&0, 3));
+        assert_eq!(vec![(0, 0), (1, 0), (10, 1), (11, 1), (12, 1)], model.nearest(&0, 7));
+
+        let testing = [(0, 0), (1, 12)];
+        let matrices = confusion_for_each_k(&testing, &model.classify_all_for_each_k(&testing, 3), 3, None);
+        assert_eq!(vec![1.0, 1.0, 1.0], matrices.iter().map(|m| m.accuracy()).collect::<Vec<_>>());
+        let matrices = confusion_for_each_k(&testing, &model.classify_all_for_each_k(&testing, 3), 3, Some(0.5));
+        assert_eq!(vec![0, 0, 1], matrices.iter().map(|m| m.rejected()).collect::<Vec<_>>());
+        assert_eq!((Some(0), None), (model.classify_or_reject(&0, 0.3), model.classify_or_reject(&0, 0.5)));
+        assert_eq!(1.0, model.test(&testing).accuracy());
+    }
+
+    #[test]
+    fn test_leave_one_out() {
+        let mut model = Knn::new(1, distance);
+        model.train(&vec![(0, 0), (0, 0), (1, 5), (0, 6), (1, 20)]);
+        // Each example finds its own duplicate at distance 0, but not itself.
+        assert_eq!(vec![0, 0, 0, 1, 0], model.leave_one_out());
+        assert_eq!(vec![(0, 1.0), (0, 1.0), (0, 1.0), (1, 1.0), (0, 1.0)], model.leave_one_out_with_margins());
+        let by_k = model.leave_one_out_for_each_k(3);
+        assert_eq!(vec![(0, 0), (0, 0), (0, 0), (1, 0), (0, 0)], by_k.iter().map(|votes| (votes[0].0, votes[2].0)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_tie_break() {
+        let neighbors = [(1.0, 3), (2.0, 2), (4.0, 2), (5.0, 3), (6.0, 1)];
+        let votes: Vec<u8> = [TieBreak::Nearest, TieBreak::TotalDistance, TieBreak::LowestLabel, TieBreak::ReduceK].iter()
+            .map(|tie_break| vote(&neighbors, Weighting::Uniform, *tie_break))
             .collect();
-        distances.sort();
+        assert_eq!(vec![3, 3, 2, 2], votes);
+        let votes = votes_for_each_k(&neighbors, 6, Weighting::Uniform, TieBreak::ReduceK);
+        assert_eq!(vec![(3, 1.0), (3, 0.0), (2, 1.0 / 3.0), (2, 0.0), (2, 0.0), (2, 0.0)], votes);
+        assert_eq!(Some(TieBreak::ReduceK), TieBreak::from_name(&TieBreak::ReduceK.to_string()));
+    }
 
-        let mut labels = HashHistogram::new();
-        for item in distances.iter().take(self.k) {
-            labels.bump(item.1);
-        }
-        /*for i in 0..self.k {
-            labels.bump(distances[i].1);
-        }*/
-        labels.mode()
+    #[test]
+    #[should_panic(expected = "cannot vote without neighbors")]
+    fn test_no_neighbors() {
+        Knn::new(1, distance).classify(&0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_weighting() {
+        let neighbors = [(1.0, 0), (4.0, 1), (5.0, 1)];
+        let votes: Vec<u8> = [Weighting::Uniform, Weighting::InverseDistance, Weighting::InverseSquared, Weighting::Gaussian, Weighting::Dudani].iter()
+            .map(|weighting| vote(&neighbors, *weighting, TieBreak::Nearest))
+            .collect();
+        assert_eq!(vec![1, 0, 0, 1, 0], votes);
+        let neighbors = [(1.0, 2), (2.0, 0), (3.0, 1), (3.0, 0), (5.0, 1), (6.0, 2), (8.0, 1)];
+        let votes: Vec<u8> = votes_for_each_k(&neighbors, 7, Weighting::Uniform, TieBreak::TotalDistance).iter().map(|(label, _)| *label).collect();
+        assert_eq!(vec![2, 2, 2, 0, 0, 0, 1], votes);
+        assert_eq!(0, vote(&neighbors, Weighting::Dudani, TieBreak::Nearest));
+        assert_eq!(vec![(0, 1.0), (1, 0.25), (2, 0.0)], scores(&[(1.0, 0), (4.0, 1), (5.0, 2)], Weighting::Dudani));
+        assert_eq!(vec![(0, 1.0), (1, 0.0), (2, 0.0)], scores(&[(0.0, 0), (1.0, 1), (1.0, 2)], Weighting::InverseDistance));
+        assert_eq!(Some(Weighting::InverseSquared), Weighting::from_name(&Weighting::InverseSquared.to_string()));
+
+        let mut model = Knn::new(3, |a: &u32, b: &u32| a.abs_diff(*b)).with_weighting(Weighting::InverseDistance);
+        model.train(&vec![(0, 10), (1, 13), (1, 14)]);
+        assert_eq!(0, model.classify(&10));
+
+        // Weighted by squared distances, 1/4 would outvote 1/9 + 1/9.
+        let mut model = Knn::new(3, euclidean_distance).with_weighting(Weighting::InverseDistance).with_true_distance(f64::sqrt);
+        model.train(&[(0, 2), (1, 3), (1, 3)].iter().map(|(label, value)| (*label, Image::from_vec(&vec![*value]))).collect());
+        assert_eq!(1, model.classify(&Image::from_vec(&vec![0])));
+    }
+
+    #[test]
+    fn test_label_scores() {
+        let mut model = Knn::new(4, distance);
+        model.train(&vec![(1, 10), (0, 0), (1, 11), (0, 1), (1, 12), (2, 2)]);
+        assert_eq!(vec![(0, 0.5), (2, 0.25), (1, 0.25)], model.label_scores(&0));
+        assert_eq!((0, 0.25), model.classify_with_margin(&0));
+        assert_eq!(vec![(0, 0.25), (1, 0.5)], model.classify_all_with_margins(&[(0, 0), (1, 12)]));
+
+        let model = model.with_weighting(Weighting::Dudani);
+        let scores = model.label_scores(&0);
+        assert_eq!(vec![0, 2, 1], scores.iter().map(|(label, _)| *label).collect::<Vec<_>>());
+        assert_eq!(0.0, scores[2].1);
+    }
+}
@@ -10,30 +10,96 @@ mod patch;
 mod convolutional;
 mod bits;
 mod timing;
+mod idx;
+mod datasets;
+mod cifar;
+mod netpbm;
+mod cache;
+mod sampling;
+mod stats;
+mod seeds;
+mod bias;
+mod results;
+mod report;
+mod options;
+mod variants;
+mod config;
+mod sweep;
+mod parallel;
 
 use std::io;
-use crate::training_harness::Classifier;
-use crate::mnist_data::Image;
+use crate::training_harness::{Classifier, ConfusionMatrix, reject_below};
+use crate::mnist_data::{Image, Grid};
 use std::env;
-use std::collections::{HashSet, BTreeMap, HashMap};
+use std::process;
+use std::collections::{BTreeMap, HashMap};
 use crate::brief::Descriptor;
-use crate::convolutional::{kernelize_all, kernelized_distance};
+use crate::convolutional::{kernelize_all, kernelized_distance, kernelized_distance_within};
+use crate::knn::{BoundedDistance, TieBreak, Weighting};
 use crate::patch::patchify;
-use crate::timing::print_time_milliseconds;
+use crate::timing::{print_time_milliseconds, print_and_time_milliseconds, time_milliseconds};
+use crate::datasets::{DataFiles, Dataset};
+use crate::cache::{Cache, Cacheable, fingerprint, file_signature};
+use crate::sampling::{Sampler, Sampling, Split};
+use crate::hash_histogram::HashHistogram;
+use rand::rngs::StdRng;
+use crate::bias::{BiasReport, Significance};
+use crate::results::{RunResults, ResultRecord, VariantResult};
+use crate::options::{Args, OptionSpec, Section, invalid_input};
+use crate::variants::{Variant, Features, Distance, Pairs, Defaults};
+use crate::sweep::{SweepCell, SweepGrid};
 
 const SHRINK_SEQUENCE: [usize; 5] = [50, 20, 10, 5, 2];
 
-const BASE_PATH: &str = "/Users/ferrer/Desktop/mnist_data/";
 const SHRINK_FACTOR: usize = 50;
 const K: usize = 7;
 const PATCH_SIZE: usize = 3;
 const NUM_NEIGHBORS: usize = 8;
-const CLASSIC_BRIEF_PAIRS: usize = mnist_data::IMAGE_DIMENSION * mnist_data::IMAGE_DIMENSION * NUM_NEIGHBORS;
+const GAUSSIAN_DIVISOR: usize = 3;
+const GAUSSIAN_7_DIVISOR: usize = 7;
+const PERMUTATION_FILE: &str = "image_permutation_file";
 
 const HELP: &str = "help";
+const REPORT: &str = "report";
 const SHRINK: &str = "shrink";
 const PERMUTE: &str = "permute";
 const SEQ: &str = "sequence";
+const EXPORT_PGM: &str = "--export-pgm";
+const CACHE: &str = "--cache";
+const SAMPLING: &str = "--sampling";
+const PER_CLASS: &str = "--per-class";
+const VALIDATION: &str = "--validation";
+const SEED: &str = "--seed";
+const SAMPLE_SEED: &str = "--sample-seed";
+const FOLDS: &str = "--folds";
+const LOOCV: &str = "loocv";
+const THREADS: &str = "--threads";
+const TRIALS: &str = "--trials";
+const OUTPUT: &str = "--output";
+const FORMAT: &str = "--format";
+const CONFIG: &str = "--config";
+const SHRINK_BY: &str = "--shrink";
+const SHRINK_SEQUENCE_OPTION: &str = "--shrink-sequence";
+const PERMUTATION_FILE_OPTION: &str = "--permutation-file";
+const K_OPTION: &str = "--k";
+const K_MAX: &str = "--k-max";
+const WEIGHTING: &str = "--weighting";
+const TIE_BREAK: &str = "--tie-break";
+const REJECT: &str = "--reject";
+const NEIGHBORS: &str = "--neighbors";
+const PATCH_SIZE_OPTION: &str = "--patch-size";
+const BRIEF_PAIRS: &str = "--brief-pairs";
+const GAUSSIAN_DIVISOR_OPTION: &str = "--gaussian-divisor";
+const GAUSSIAN_7_DIVISOR_OPTION: &str = "--gaussian-7-divisor";
+const SWEEP_K: &str = "--sweep-k";
+const SWEEP_NEIGHBORS: &str = "--sweep-neighbors";
+const SWEEP_BRIEF_PAIRS: &str = "--sweep-brief-pairs";
+const SWEEP_PATCH_SIZE: &str = "--sweep-patch-size";
+const SWEEP_GAUSSIAN_DIVISOR: &str = "--sweep-gaussian-divisor";
+
+/// Each sweep option with the name of the parameter it sweeps.
+const SWEEP_OPTIONS: [(&str, &str); 5] = [(SWEEP_K, "k"), (SWEEP_NEIGHBORS, "neighbors"), (SWEEP_BRIEF_PAIRS, "brief pairs"),
+                                          (SWEEP_PATCH_SIZE, "patch size"), (SWEEP_GAUSSIAN_DIVISOR, "gaussian divisor")];
 
 const BASELINE: &str = "baseline";
 const BRIEF: &str = "brief";
@@ -44,185 +110,1059 @@ const UNIFORM_NEIGHBORS: &str = "uniform_neighbors";
 const GAUSSIAN_NEIGHBORS: &str = "gaussian_neighbors";
 const GAUSSIAN_7: &str = "gaussian_7";
 
-fn main() -> io::Result<()> {
-    let args: HashSet<String> = env::args().collect();
-    if args.contains(HELP) {
-        help_message();
+/// The variants in order of appearance in Tables 1 and 2 of the paper.
+const PAPER_VARIANTS: [&str; 8] = [BASELINE, CONVOLUTIONAL_1, UNIFORM_BRIEF, BRIEF, PATCH, UNIFORM_NEIGHBORS, GAUSSIAN_NEIGHBORS, GAUSSIAN_7];
+
+fn main() {
+    let arg_list: Vec<String> = env::args().collect();
+    let sections = option_sections();
+    let outcome = if arg_list.iter().any(|arg| arg == HELP) {
+        print!("{}", options::usage("flairs33", &sections));
+        Ok(())
+    } else if arg_list.get(1).map(|s| s.as_str()) == Some(REPORT) {
+        make_report(&arg_list[2..])
     } else {
-        train_and_test(&args)?;
+        Args::parse(&arg_list[1..], &sections).and_then(|args| load_and_run(&arg_list, args))
+    };
+    if let Err(e) = outcome {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn load_and_run(arg_list: &[String], args: Args) -> io::Result<()> {
+    let (args, config_variants) = match args.value(CONFIG) {
+        Some(file_name) => {
+            let config = config::read_config(&file_name)?;
+            let file_args = Args::parse(&config.options, &option_sections()).map_err(|e| invalid_input(format!("{}: {}", file_name, e)))?;
+            if file_args.value(CONFIG).is_some() {
+                return Err(invalid_input(format!("{}: {} can only be given on the command line", file_name, CONFIG)));
+            }
+            (file_args.overridden_by(&args), config.variants)
+        }
+        None => (args, Vec::new())
+    };
+    let args = &args;
+    let cache = match args.value(CACHE) {
+        Some(dir) => Some(Cache::new(&dir)?),
+        None => None
+    };
+    let sampling = match (args.value(SAMPLING), args.parse_at_least(PER_CLASS, 1)?) {
+        (_, Some(per_class)) => Sampling::PerClass(per_class),
+        (None, None) => Sampling::EveryNth,
+        (Some(name), None) => Sampling::from_name(&name).ok_or_else(|| invalid_input(format!("unknown sampling method \"{}\"", name)))?
+    };
+    if args.contains(SEQ) && matches!(sampling, Sampling::PerClass(_)) {
+        return Err(invalid_input(format!("{} cannot be combined with {}", PER_CLASS, SEQ)));
+    }
+    let validation: Option<f64> = args.parse_value(VALIDATION)?;
+    if validation.is_some_and(|v| v <= 0.0 || v >= 1.0) {
+        return Err(invalid_input(format!("{} must be between 0 and 1", VALIDATION)));
+    }
+    let folds: Option<usize> = args.parse_at_least(FOLDS, 2)?;
+    let leave_one_out = args.contains(LOOCV);
+    if leave_one_out && (folds.is_some() || validation.is_some()) {
+        return Err(invalid_input(format!("{} cannot be combined with {} or {}", LOOCV, FOLDS, VALIDATION)));
+    }
+    let trials = args.parse_at_least(TRIALS, 1)?.unwrap_or(1);
+    let threads = args.parse_at_least(THREADS, 1)?.unwrap_or_else(parallel::default_threads);
+    let params = Parameters::from_args(args)?;
+    let mut swept = Vec::new();
+    for (option, name) in SWEEP_OPTIONS.iter() {
+        if let Some(values) = args.parse_ranges(option, 1)? {
+            swept.push((*option, *name, values));
+        }
+    }
+    let mut sweep_points = Vec::new();
+    for values in sweep::combinations(&swept.iter().map(|(_, _, values)| values.clone()).collect::<Vec<_>>()) {
+        let mut point_params = params.clone();
+        swept.iter().zip(values.iter()).for_each(|((option, _, _), value)| point_params.set(option, *value));
+        let variants = select_variants(args, &point_params, &config_variants)?;
+        sweep_points.push(SweepPoint {values, variants});
+    }
+    let files = DataFiles::from_args(args)?;
+    files.check_present()?;
+    let seed = args.parse_value(SEED)?.unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    let sample_seed = args.parse_value(SAMPLE_SEED)?.unwrap_or_else(|| seeds::derive_seed(seed, "sampling"));
+    if sampling != Sampling::EveryNth || validation.is_some() || folds.is_some() {
+        println!("Sampling: {}; sampling seed: {}", sampling, sample_seed);
+    }
+    let mut configuration = vec![("arguments".to_string(), arg_list[1..].join(" ")),
+                                 ("dataset".to_string(), files.dataset.to_string()),
+                                 ("k".to_string(), params.k.to_string()),
+                                 ("weighting".to_string(), params.weighting.to_string()),
+                                 ("tie break".to_string(), params.tie_break.to_string()),
+                                 ("neighbors".to_string(), params.neighbors.to_string()),
+                                 ("patch size".to_string(), params.patch_size.to_string()),
+                                 ("sampling".to_string(), sampling.to_string()),
+                                 ("sampling seed".to_string(), sample_seed.to_string()),
+                                 ("trials".to_string(), trials.to_string()),
+                                 ("threads".to_string(), threads.to_string())];
+    if let Some(fraction) = validation {
+        configuration.push(("validation".to_string(), fraction.to_string()));
+    }
+    if let Some(k) = folds {
+        configuration.push(("folds".to_string(), k.to_string()));
+    }
+    if leave_one_out {
+        configuration.push(("evaluation".to_string(), "leave-one-out".to_string()));
+    }
+    if let Some(margin) = params.reject {
+        configuration.push(("reject".to_string(), margin.to_string()));
+    }
+    if let Some(pairs) = params.brief_pairs {
+        configuration.push(("brief pairs".to_string(), pairs.to_string()));
+    }
+    if let Some(file_name) = args.value(CONFIG) {
+        configuration.push(("config".to_string(), file_name));
+    }
+    if swept.is_empty() {
+        for variant in sweep_points[0].variants.iter() {
+            configuration.push((format!("variant {}", variant.name), variant.to_string()));
+        }
+    }
+    for (_, name, values) in swept.iter() {
+        configuration.push((format!("sweep {}", name), values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",")));
+    }
+    let output = args.value(OUTPUT);
+    let mut settings = RunSettings {
+        export_dir: args.value(EXPORT_PGM),
+        cache,
+        sampler: Sampler::new(sampling, sample_seed),
+        validation,
+        folds,
+        leave_one_out,
+        threads,
+        seed,
+        trials,
+        results: RunResults::new(configuration, seed),
+        params,
+        swept: swept.iter().map(|(_, name, _)| name.to_string()).collect(),
+        sweep_points
+    };
+    train_and_test(args, &files, &mut settings)?;
+    if let Some(file_name) = output {
+        settings.results.write(&file_name)?;
+        println!("Wrote results to {}", file_name);
     }
     Ok(())
 }
 
-fn help_message() {
-    println!("Usage: flairs33 [options]:");
-    println!("\t{}: print this message", HELP);
-    println!("\t{}: runs additional experiment that permutes image pixels", PERMUTE);
-    println!("\t{}: Use only 1 out of {} training/testing images", SHRINK, SHRINK_FACTOR);
-    println!("\t{}: Use 1/50, 1/20, 1/10, 1/5, and 1/2 training/testing images", SEQ);
-    println!("\nAlgorithmic options:");
-    println!("The eight variants of the paper are given in order of appearance in Tables 1 and 2.");
-    println!("All variants describe a knn (k=7) distance function variation:");
-    println!("\t{}: Euclidean", BASELINE);
-    println!("\t{}: Convolutional Euclidean (1 level)", CONVOLUTIONAL_1);
-    println!("\t{}: Uniform Classical BRIEF descriptors", UNIFORM_BRIEF);
-    println!("\t{}: Gaussian Classical BRIEF descriptors", BRIEF);
-    println!("\t{}: 3x3 Neighbor BRIEF descriptors", PATCH);
-    println!("\t{}: Uniform neighbor BRIEF", UNIFORM_NEIGHBORS);
-    println!("\t{}: Gaussian neighbor BRIEF (stdev 1/3 side)", GAUSSIAN_NEIGHBORS);
-    println!("\t{}: Gaussian neighbor BRIEF (stdev 1/7 side)", GAUSSIAN_7);
-}
-
-fn train_and_test(args: &HashSet<String>) -> io::Result<()> {
-    let mut training_images = load_data_set("train")?;
-    let mut testing_images = load_data_set("t10k")?;
+/// The paper's variants selected by `args`, followed by the variants of the configuration file.
+fn select_variants(args: &Args, params: &Parameters, config_variants: &[(String,BTreeMap<String,String>)]) -> io::Result<Vec<Variant>> {
+    let mut variants: Vec<Variant> = params.paper_variants().into_iter().filter(|variant| args.contains(&variant.name)).collect();
+    for (name, settings) in config_variants.iter() {
+        if PAPER_VARIANTS.contains(&name.as_str()) {
+            return Err(invalid_input(format!("{}: variant {} has the name of one of the paper's variants", args.value(CONFIG).unwrap(), name)));
+        }
+        variants.push(Variant::from_settings(name, settings, &params.variant_defaults())
+            .map_err(|e| invalid_input(format!("{}: {}", args.value(CONFIG).unwrap(), e)))?);
+    }
+    Ok(variants)
+}
+
+/// Prints tables of the error rates in the CSV results files named in `arg_list`.
+fn make_report(arg_list: &[String]) -> io::Result<()> {
+    let mut format = None;
+    let mut file_names = Vec::new();
+    let mut remaining = arg_list.iter();
+    while let Some(arg) = remaining.next() {
+        if arg == FORMAT {
+            let value = remaining.next().ok_or_else(|| invalid_input(format!("{} needs a value", FORMAT)))?;
+            if format.replace(value.as_str()).is_some() {
+                return Err(invalid_input(format!("{} is given more than once", FORMAT)));
+            }
+        } else {
+            file_names.push(arg);
+        }
+    }
+    let format = format.unwrap_or("both");
+    if !["markdown", "latex", "both"].contains(&format) {
+        return Err(invalid_input(format!("unknown report format \"{}\"", format)));
+    }
+    if file_names.is_empty() {
+        return Err(invalid_input(format!("{} needs at least one CSV results file", REPORT)));
+    }
+    let mut results = Vec::new();
+    for file_name in file_names {
+        results.append(&mut report::read_csv_results(file_name)?);
+    }
+    let table = report::ErrorTable::new(&results, &PAPER_VARIANTS);
+    if format != "latex" {
+        print!("{}", table.to_markdown());
+    }
+    if format == "both" {
+        println!();
+    }
+    if format != "markdown" {
+        print!("{}", table.to_latex());
+    }
+    Ok(())
+}
+
+/// The experiment parameters that can be changed from the command line.
+#[derive(Clone)]
+pub struct Parameters {
+    k: usize,
+    /// Also find the error rate of every k up to this one.
+    k_max: Option<usize>,
+    weighting: Weighting,
+    tie_break: TieBreak,
+    /// Abstain from classifications with a smaller margin between the two most likely labels.
+    reject: Option<f64>,
+    shrink: Option<usize>,
+    shrink_sequence: Vec<usize>,
+    neighbors: usize,
+    patch_size: usize,
+    /// Pairs in the classic BRIEF descriptors; by default, `neighbors` per pixel.
+    brief_pairs: Option<usize>,
+    gaussian_divisor: usize,
+    gaussian_7_divisor: usize,
+    permutation_file: String,
+}
+
+impl Parameters {
+    fn from_args(args: &Args) -> io::Result<Parameters> {
+        let shrink = match args.parse_at_least(SHRINK_BY, 1)? {
+            Some(factor) => Some(factor),
+            None if args.contains(SHRINK) => Some(SHRINK_FACTOR),
+            None => None
+        };
+        let reject: Option<f64> = args.parse_value(REJECT)?;
+        if matches!(reject, Some(margin) if !(0.0..=1.0).contains(&margin)) {
+            return Err(invalid_input(format!("{} must be between 0 and 1", REJECT)));
+        }
+        Ok(Parameters {
+            k: args.parse_at_least(K_OPTION, 1)?.unwrap_or(K),
+            k_max: args.parse_at_least(K_MAX, 1)?,
+            weighting: match args.value(WEIGHTING) {
+                None => Weighting::Uniform,
+                Some(name) => Weighting::from_name(&name).ok_or_else(|| invalid_input(format!("unknown weighting \"{}\"", name)))?
+            },
+            tie_break: match args.value(TIE_BREAK) {
+                None => TieBreak::Nearest,
+                Some(name) => TieBreak::from_name(&name).ok_or_else(|| invalid_input(format!("unknown tie-breaking method \"{}\"", name)))?
+            },
+            reject,
+            shrink,
+            shrink_sequence: args.parse_list(SHRINK_SEQUENCE_OPTION, 1)?.unwrap_or_else(|| SHRINK_SEQUENCE.to_vec()),
+            neighbors: args.parse_at_least(NEIGHBORS, 1)?.unwrap_or(NUM_NEIGHBORS),
+            patch_size: args.parse_at_least(PATCH_SIZE_OPTION, 1)?.unwrap_or(PATCH_SIZE),
+            brief_pairs: args.parse_at_least(BRIEF_PAIRS, 1)?,
+            gaussian_divisor: args.parse_at_least(GAUSSIAN_DIVISOR_OPTION, 1)?.unwrap_or(GAUSSIAN_DIVISOR),
+            gaussian_7_divisor: args.parse_at_least(GAUSSIAN_7_DIVISOR_OPTION, 1)?.unwrap_or(GAUSSIAN_7_DIVISOR),
+            permutation_file: args.value(PERMUTATION_FILE_OPTION).unwrap_or_else(|| PERMUTATION_FILE.to_string())
+        })
+    }
+
+    /// Sets the parameter swept by `option`.
+    fn set(&mut self, option: &str, value: usize) {
+        match option {
+            SWEEP_K => self.k = value,
+            SWEEP_NEIGHBORS => self.neighbors = value,
+            SWEEP_BRIEF_PAIRS => self.brief_pairs = Some(value),
+            SWEEP_PATCH_SIZE => self.patch_size = value,
+            SWEEP_GAUSSIAN_DIVISOR => self.gaussian_divisor = value,
+            _ => panic!("{} is not a sweep option", option)
+        }
+    }
+
+    /// The variants of the paper, in the order of `PAPER_VARIANTS`.
+    fn paper_variants(&self) -> Vec<Variant> {
+        let pairs = self.variant_defaults().pairs;
+        vec![
+            Variant::new(BASELINE, Features::Raw, self.k),
+            Variant::new(CONVOLUTIONAL_1, Features::Convolutional {levels: 1}, self.k),
+            Variant::new(UNIFORM_BRIEF, Features::UniformBrief {pairs}, self.k),
+            Variant::new(BRIEF, Features::GaussianBrief {pairs}, self.k),
+            Variant::new(PATCH, Features::Patch {size: self.patch_size}, self.k),
+            Variant::new(UNIFORM_NEIGHBORS, Features::UniformNeighbors {neighbors: self.neighbors}, self.k),
+            Variant::new(GAUSSIAN_NEIGHBORS, Features::GaussianNeighbors {neighbors: self.neighbors, divisor: self.gaussian_divisor}, self.k),
+            Variant::new(GAUSSIAN_7, Features::GaussianNeighbors {neighbors: self.neighbors, divisor: self.gaussian_7_divisor}, self.k),
+        ]
+    }
+
+    /// Settings of configuration file variants default to the values of the paper's variants.
+    fn variant_defaults(&self) -> Defaults {
+        Defaults {
+            k: self.k,
+            pairs: self.brief_pairs.map_or(Pairs::PerPixel(self.neighbors), Pairs::Total),
+            neighbors: self.neighbors,
+            patch_size: self.patch_size,
+            gaussian_divisor: self.gaussian_divisor
+        }
+    }
+}
+
+struct RunSettings {
+    export_dir: Option<String>,
+    cache: Option<Cache>,
+    sampler: Sampler,
+    validation: Option<f64>,
+    folds: Option<usize>,
+    leave_one_out: bool,
+    threads: usize,
+    seed: u64,
+    trials: usize,
+    results: RunResults,
+    params: Parameters,
+    /// Names of the swept parameters; empty unless sweeping.
+    swept: Vec<String>,
+    sweep_points: Vec<SweepPoint>,
+}
+
+/// The values of the swept parameters, in the order of `RunSettings::swept`, and the
+/// variants they give.
+struct SweepPoint {
+    values: Vec<usize>,
+    variants: Vec<Variant>,
+}
+
+impl RunSettings {
+    /// The first trial uses the global seed itself, so a single trial reproduces the run
+    /// that printed it.
+    fn trial_seed(&self, trial: usize) -> u64 {
+        if trial == 0 {self.seed} else {seeds::derive_seed(self.seed, &format!("trial {}", trial))}
+    }
+
+    fn record_results(&mut self, shrink: Option<usize>, trial: usize, data: &ExperimentData, permuted: bool) {
+        for result in data.variant_results.iter() {
+            self.results.records.push(ResultRecord {shrink, trial, seed: data.seed, permuted, result: result.clone()});
+        }
+    }
+}
+
+fn option_sections() -> Vec<Section> {
+    vec![
+        Section {title: "General options", notes: vec![], options: vec![
+            OptionSpec::flag(HELP, "print this message".to_string()),
+            OptionSpec::flag(PERMUTE, "runs additional experiment that permutes image pixels".to_string()),
+            OptionSpec::flag(SHRINK, format!("Use only 1 out of {} training/testing images", SHRINK_FACTOR)),
+            OptionSpec::valued(SHRINK_BY, "n", "Use only 1 out of n training/testing images".to_string()),
+            OptionSpec::flag(SEQ, format!("Use 1/n training/testing images for each n in the shrink sequence (default: {})",
+                                          SHRINK_SEQUENCE.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(","))),
+            OptionSpec::valued(SHRINK_SEQUENCE_OPTION, "n,n,...", format!("the shrink factors used by {}", SEQ)),
+            OptionSpec::valued(SAMPLING, "method", format!("how {} and {} choose images: every (every n-th image; default), stratified (same fraction of each class), random", SHRINK, SEQ)),
+            OptionSpec::valued(PER_CLASS, "n", "use n randomly chosen training/testing images of each class".to_string()),
+            OptionSpec::valued(VALIDATION, "fraction", "hold out this fraction of each training class and test on it instead of the testing images, which are then not used at all".to_string()),
+            OptionSpec::valued(FOLDS, "k", "cross-validate on k stratified folds of the training images instead of using the testing images".to_string()),
+            OptionSpec::flag(LOOCV, "classify each training image by its nearest neighbors among the others (leave-one-out cross-validation) instead of using the testing images".to_string()),
+            OptionSpec::valued(TRIALS, "n", "repeat the experiments n times with newly generated descriptors and kernels, and summarize the error rates".to_string()),
+            OptionSpec::valued(SEED, "n", "seed for all random choices: descriptors, kernels and sampling (default: chosen at random and printed)".to_string()),
+            OptionSpec::valued(SAMPLE_SEED, "n", format!("seed for random sampling only, overriding the one derived from {}", SEED)),
+            OptionSpec::valued(PERMUTATION_FILE_OPTION, "file", format!("comma-separated pixel permutation used by {}, with an entry for each pixel of the images (default: {}, for 28x28 images)", PERMUTE, PERMUTATION_FILE)),
+            OptionSpec::valued(CONFIG, "file", "read options and additional variants from file; options given on the command line take precedence".to_string()),
+            OptionSpec::valued(OUTPUT, "file", "write the configuration, seeds, error rates, confusion matrices and times of every experiment to file, as CSV or JSON depending on whether it ends in .csv or .json".to_string()),
+            OptionSpec::valued(THREADS, "n", "classify and convert images on n threads; results are the same for any n (default: one per CPU core)".to_string()),
+            OptionSpec::valued(CACHE, "dir", "cache loaded datasets, descriptors and converted images in dir to reuse in later runs".to_string()),
+            OptionSpec::valued(EXPORT_PGM, "dir", format!("write the training/testing images (and permuted images, with {}) as PGM files under dir", PERMUTE)),
+        ]},
+        Section {title: "Reports", options: vec![], notes: vec![
+            format!("\t{} [{} markdown|latex] file...: print tables of the error rates in CSV files written by {}, with a row for each variant and a column for each shrink factor and condition (default: both formats)", REPORT, FORMAT, OUTPUT),
+        ]},
+        Section {title: "Data options", notes: vec![], options: datasets::option_specs()},
+        Section {title: "Algorithmic options", notes: vec![
+            "The eight variants of the paper are given in order of appearance in Tables 1 and 2.".to_string(),
+            format!("All variants describe a knn (k={} unless {} is given) distance function variation:", K, K_OPTION),
+        ], options: vec![
+            OptionSpec::flag(BASELINE, "Euclidean".to_string()),
+            OptionSpec::flag(CONVOLUTIONAL_1, "Convolutional Euclidean (1 level)".to_string()),
+            OptionSpec::flag(UNIFORM_BRIEF, "Uniform Classical BRIEF descriptors".to_string()),
+            OptionSpec::flag(BRIEF, "Gaussian Classical BRIEF descriptors".to_string()),
+            OptionSpec::flag(PATCH, format!("{0}x{0} Neighbor BRIEF descriptors (size set by {1})", PATCH_SIZE, PATCH_SIZE_OPTION)),
+            OptionSpec::flag(UNIFORM_NEIGHBORS, "Uniform neighbor BRIEF".to_string()),
+            OptionSpec::flag(GAUSSIAN_NEIGHBORS, format!("Gaussian neighbor BRIEF (stdev 1/{} side)", GAUSSIAN_DIVISOR)),
+            OptionSpec::flag(GAUSSIAN_7, format!("Gaussian neighbor BRIEF (stdev 1/{} side)", GAUSSIAN_7_DIVISOR)),
+            OptionSpec::valued(K_OPTION, "n", format!("number of nearest neighbors that vote (default: {})", K)),
+            OptionSpec::valued(K_MAX, "n", "also report the error rate of every k from 1 to n, from the same search for nearest neighbors".to_string()),
+            OptionSpec::valued(WEIGHTING, "scheme", "how much each neighbor's vote counts, given its distance d: uniform (default), inverse (1/d), inverse-squared (1/d^2), gaussian (exp(-d^2/2s^2), s the distance of the k-th neighbor), dudani (from 1 for the nearest to 0 for the k-th)".to_string()),
+            OptionSpec::valued(REJECT, "margin", "abstain when the vote fractions of the two most likely labels differ by less than margin, reporting the error rate of the other images and the number rejected, and print the coverage and accuracy of each margin".to_string()),
+            OptionSpec::valued(TIE_BREAK, "method", "how tied votes are decided: nearest (label of the nearest tied neighbor; default), distance (smallest total distance), label (lowest label), reduce-k (drop the farthest neighbors until untied)".to_string()),
+            OptionSpec::valued(NEIGHBORS, "n", format!("pairs per pixel in the neighbor BRIEF descriptors (default: {})", NUM_NEIGHBORS)),
+            OptionSpec::valued(BRIEF_PAIRS, "n", format!("pairs in the classic BRIEF descriptors (default: the value of {} times the number of pixels)", NEIGHBORS)),
+            OptionSpec::valued(PATCH_SIZE_OPTION, "n", format!("side of the neighborhood compared with each pixel by {} (default: {})", PATCH, PATCH_SIZE)),
+            OptionSpec::valued(GAUSSIAN_DIVISOR_OPTION, "n", format!("{} uses a stdev of 1/n of the image side (default: {})", GAUSSIAN_NEIGHBORS, GAUSSIAN_DIVISOR)),
+            OptionSpec::valued(GAUSSIAN_7_DIVISOR_OPTION, "n", format!("{} uses a stdev of 1/n of the image side (default: {})", GAUSSIAN_7, GAUSSIAN_7_DIVISOR)),
+        ]},
+        Section {title: "Sweep options", notes: vec![
+            "Run the selected variants for every combination of the values given, and print a grid of their error rates.".to_string(),
+            "Values are comma-separated numbers and ranges: 1-9 includes both ends, 1-9:2 steps by 2.".to_string(),
+        ], options: vec![
+            OptionSpec::valued(SWEEP_K, "values", format!("values of {}", K_OPTION)),
+            OptionSpec::valued(SWEEP_NEIGHBORS, "values", format!("values of {}", NEIGHBORS)),
+            OptionSpec::valued(SWEEP_BRIEF_PAIRS, "values", format!("values of {}", BRIEF_PAIRS)),
+            OptionSpec::valued(SWEEP_PATCH_SIZE, "values", format!("values of {}", PATCH_SIZE_OPTION)),
+            OptionSpec::valued(SWEEP_GAUSSIAN_DIVISOR, "values", format!("values of {}", GAUSSIAN_DIVISOR_OPTION)),
+        ]},
+    ]
+}
+
+fn train_and_test(args: &Args, files: &DataFiles, settings: &mut RunSettings) -> io::Result<()> {
+    let training_images = load_data_set(files.dataset, "train", &files.training, &settings.cache)?;
+    let testing_images = load_data_set(files.dataset, "test", &files.testing, &settings.cache)?;
+    files.check_loaded(&training_images, &testing_images)?;
+    let class_names = match files.dataset {
+        Dataset::Folders(_) => {
+            let names = netpbm::class_names(&files.training)?;
+            println!("Classes: {}", names.iter().enumerate().map(|(i, name)| format!("{}={}", i, name)).collect::<Vec<_>>().join(", "));
+            Some(names)
+        }
+        _ => None
+    };
 
     if args.contains(SEQ) {
-        for shrink in SHRINK_SEQUENCE.iter() {
+        if let Some(dir) = &settings.export_dir {
+            export_pgm(args, &settings.params.permutation_file, dir, &training_images, &testing_images, class_names.as_deref())?;
+        }
+        for shrink in settings.params.shrink_sequence.clone() {
             println!("Shrinking by {}", shrink);
-            run_experiments(args, mnist_data::discard(&training_images, *shrink),
-                            mnist_data::discard(&testing_images, *shrink))?;
+            let (training, testing) = sample_data(settings, &training_images, &testing_images, Some(shrink));
+            run_sweep(args, Some(shrink), training, testing, settings)?;
         }
 
     } else {
-        if args.contains(SHRINK) {
-            println!("Shrinking by {}", SHRINK_FACTOR);
-            training_images = mnist_data::discard(&training_images, SHRINK_FACTOR);
-            testing_images = mnist_data::discard(&testing_images, SHRINK_FACTOR);
+        let shrink = match settings.sampler.method() {
+            Sampling::PerClass(n) => {
+                println!("Sampling {} images per class", n);
+                Some(1)
+            }
+            _ => {
+                settings.params.shrink.iter().for_each(|shrink| println!("Shrinking by {}", shrink));
+                settings.params.shrink
+            }
+        };
+        let (training, testing) = sample_data(settings, &training_images, &testing_images, shrink);
+
+        if let Some(dir) = &settings.export_dir {
+            export_pgm(args, &settings.params.permutation_file, dir, &training, &testing, class_names.as_deref())?;
         }
+        run_sweep(args, shrink.filter(|s| *s > 1), training, testing, settings)?;
+    }
 
-        run_experiments(args, training_images, testing_images)?;
+    Ok(())
+}
+
+fn sample_data(settings: &mut RunSettings, training_images: &[(u8,Image)], testing_images: &[(u8,Image)], shrink: Option<usize>) -> Split<Image> {
+    let (mut training, mut testing) = match shrink {
+        Some(shrink) => (settings.sampler.shrink(training_images, shrink), settings.sampler.shrink(testing_images, shrink)),
+        None => (training_images.to_vec(), testing_images.to_vec())
+    };
+    if let Some(fraction) = settings.validation {
+        let (kept, validation) = settings.sampler.split(&training, fraction);
+        println!("Holding out {} of {} training images for validation", validation.len(), training.len());
+        training = kept;
+        testing = validation;
+    }
+    if shrink.is_some() || settings.validation.is_some() {
+        println!("Training labels: {}", label_counts(&training));
+        println!("Testing labels: {}", label_counts(&testing));
     }
+    (training, testing)
+}
+
+fn label_counts(images: &[(u8,Image)]) -> HashHistogram<u8> {
+    let mut counts = HashHistogram::new();
+    images.iter().for_each(|(label, _)| counts.bump(*label));
+    counts
+}
 
+/// Runs the experiments for each sweep point, on the same images and folds. Points that
+/// differ only in k are run together, so that each variant searches for its nearest
+/// neighbors once for all of the swept values of k.
+fn run_sweep(args: &Args, shrink: Option<usize>, training_images: Vec<(u8,Image)>, testing_images: Vec<(u8,Image)>, settings: &mut RunSettings) -> io::Result<()> {
+    let folds = match settings.folds {
+        Some(k) => {
+            check_folds(&training_images, k)?;
+            println!("Cross-validating with {} folds of the training images", k);
+            Some((k, settings.sampler.folds(&training_images, k)))
+        }
+        None => None
+    };
+    if settings.leave_one_out && training_images.len() < 2 {
+        return Err(invalid_input(format!("{} needs at least 2 training images, but there are {}", LOOCV, training_images.len())));
+    }
+    let k_name = SWEEP_OPTIONS.iter().find(|(option, _)| *option == SWEEP_K).unwrap().1;
+    let k_axis = settings.swept.iter().position(|name| name == k_name);
+    let mut groups: Vec<(Vec<usize>,Vec<usize>)> = Vec::new();
+    for (i, point) in settings.sweep_points.iter().enumerate() {
+        let key: Vec<usize> = point.values.iter().enumerate().filter(|(axis, _)| Some(*axis) != k_axis).map(|(_, value)| *value).collect();
+        match groups.iter_mut().find(|(group_key, _)| *group_key == key) {
+            Some((_, points)) => points.push(i),
+            None => groups.push((key, vec![i]))
+        }
+    }
+    let mut rows: Vec<Option<BTreeMap<String,SweepCell>>> = settings.sweep_points.iter().map(|_| None).collect();
+    for (_, points) in groups {
+        let mut variants: Vec<Variant> = Vec::new();
+        for variant in points.iter().flat_map(|i| settings.sweep_points[*i].variants.iter()) {
+            if !variants.contains(variant) {
+                variants.push(variant.clone());
+            }
+        }
+        if !settings.swept.is_empty() {
+            let description: Vec<String> = settings.swept.iter().enumerate()
+                .map(|(axis, name)| {
+                    let mut values: Vec<String> = points.iter().map(|i| settings.sweep_points[*i].values[axis].to_string()).collect();
+                    values.dedup();
+                    format!("{} {}", name, values.join(","))
+                })
+                .collect();
+            println!("Sweep: {}", description.join(", "));
+        }
+        let cells = run_experiments(args, shrink, training_images.clone(), testing_images.clone(), folds.clone(), variants.clone(), settings)?;
+        for i in points {
+            rows[i] = Some(settings.sweep_points[i].variants.iter()
+                .filter_map(|variant| cells.get(&variant_label(&variants, variant)).map(|cell| (variant.name.clone(), cell.clone())))
+                .collect());
+        }
+    }
+    let mut grid = SweepGrid::new(settings.swept.clone());
+    for (point, cells) in settings.sweep_points.iter().zip(rows) {
+        grid.add_row(point.values.clone(), cells.unwrap());
+    }
+    if !settings.swept.is_empty() {
+        println!();
+        println!("Sweep error rates{}{}", shrink.map_or(String::new(), |s| format!(" (shrink {})", s)),
+                 if args.contains(PERMUTE) {", original / permuted (difference, bias)"} else {""});
+        print!("{}", grid.to_markdown());
+    }
     Ok(())
 }
 
-fn run_experiments(args: &HashSet<String>, training_images: Vec<(u8,Image)>, testing_images: Vec<(u8,Image)>) -> io::Result<()> {
+/// Every fold needs at least one image to test. A class with fewer images than folds is
+/// missing from some folds, which leaves its per-class statistics undefined there.
+fn check_folds(training_images: &[(u8,Image)], num_folds: usize) -> io::Result<()> {
+    if num_folds > training_images.len() {
+        return Err(invalid_input(format!("{} {} exceeds the {} training images", FOLDS, num_folds, training_images.len())));
+    }
+    let small: Vec<String> = sampling::class_indices(training_images).iter()
+        .filter(|(_, indices)| indices.len() < num_folds)
+        .map(|(label, indices)| format!("{}:{}", label, indices.len()))
+        .collect();
+    if !small.is_empty() {
+        println!("Warning: some labels have fewer training images than the {} folds: {}", num_folds, small.join("; "));
+    }
+    Ok(())
+}
+
+/// Returns the mean original error rate of each variant, with its spatial bias when permuting.
+fn run_experiments(args: &Args, shrink: Option<usize>, training_images: Vec<(u8,Image)>, testing_images: Vec<(u8,Image)>,
+                   folds: Option<(usize,Vec<usize>)>, variants: Vec<Variant>, settings: &mut RunSettings) -> io::Result<BTreeMap<String,SweepCell>> {
+    let permutation = if args.contains(PERMUTE) {
+        Some(read_permutation_for(&settings.params.permutation_file, &training_images)?)
+    } else {
+        None
+    };
     let mut data = ExperimentData {
         training: training_images,
         testing: testing_images,
         descriptors: Default::default(),
-        errors: BTreeMap::new()
+        errors: BTreeMap::new(),
+        cache: settings.cache.clone(),
+        folds,
+        fold_errors: BTreeMap::new(),
+        outcomes: BTreeMap::new(),
+        variant_results: Vec::new(),
+        variants,
+        k_max: settings.params.k_max,
+        weighting: settings.params.weighting,
+        tie_break: settings.params.tie_break,
+        reject: settings.params.reject,
+        leave_one_out: settings.leave_one_out,
+        threads: settings.threads,
+        seed: settings.seed
     };
 
-    data.add_descriptor(BRIEF, brief::Descriptor::classic_gaussian_brief(CLASSIC_BRIEF_PAIRS, mnist_data::IMAGE_DIMENSION, mnist_data::IMAGE_DIMENSION));
-    data.add_descriptor(UNIFORM_BRIEF, brief::Descriptor::classic_uniform_brief(CLASSIC_BRIEF_PAIRS, mnist_data::IMAGE_DIMENSION, mnist_data::IMAGE_DIMENSION));
-    data.add_descriptor(UNIFORM_NEIGHBORS, brief::Descriptor::uniform_neighbor(NUM_NEIGHBORS, mnist_data::IMAGE_DIMENSION, mnist_data::IMAGE_DIMENSION));
-    data.add_descriptor(GAUSSIAN_NEIGHBORS, brief::Descriptor::gaussian_neighbor(NUM_NEIGHBORS, mnist_data::IMAGE_DIMENSION / 3, mnist_data::IMAGE_DIMENSION, mnist_data::IMAGE_DIMENSION));
-    data.add_descriptor(GAUSSIAN_7, brief::Descriptor::gaussian_neighbor(NUM_NEIGHBORS, mnist_data::IMAGE_DIMENSION / 7, mnist_data::IMAGE_DIMENSION, mnist_data::IMAGE_DIMENSION));
-
-    data.run_all_tests_with(&args);
-
-    if args.contains(PERMUTE) {
-        println!("Permuting images");
-        let permutation = permutation::read_permutation("image_permutation_file")?;
-        let mut permuted_data = data.permuted(&permutation);
-        permuted_data.run_all_tests_with(&args);
-        println!("Permuted results");
-        permuted_data.print_errors();
+    let mut original_trials = BTreeMap::new();
+    let mut permuted_trials = BTreeMap::new();
+    let mut original_outcomes = BTreeMap::new();
+    let mut permuted_outcomes = BTreeMap::new();
+    for trial in 0..settings.trials {
+        let seed = settings.trial_seed(trial);
+        if trial > 0 {
+            println!("Trial {} of {} (seed {})", trial + 1, settings.trials, seed);
+        }
+        data.start_trial(seed);
+        data.run_all_tests(trial == 0);
+
+        if let Some(permutation) = &permutation {
+            println!("Permuting images");
+            let mut permuted_data = data.permuted(permutation);
+            permuted_data.run_all_tests(trial == 0);
+            println!("Permuted results (seed {})", seed);
+            permuted_data.print_errors();
+            println!();
+            permuted_data.record_errors(&mut permuted_trials);
+            settings.record_results(shrink, trial, &permuted_data, true);
+            if trial == 0 {
+                permuted_outcomes = permuted_data.outcomes;
+            }
+        }
+
+        println!("Original results (seed {})", seed);
+        data.print_errors();
+        data.record_errors(&mut original_trials);
+        settings.record_results(shrink, trial, &data, false);
+        if trial == 0 {
+            original_outcomes = data.outcomes.clone();
+        }
+    }
+
+    if settings.trials > 1 {
+        if permutation.is_some() {
+            println!();
+            print_trial_summary(&format!("Permuted results over {} trials", settings.trials), &permuted_trials);
+        }
         println!();
+        print_trial_summary(&format!("Original results over {} trials", settings.trials), &original_trials);
     }
 
-    println!("Original results");
-    data.print_errors();
-    Ok(())
+    let mut cells: BTreeMap<String,SweepCell> = original_trials.iter()
+        .map(|(label, original_errors)| (label.clone(), SweepCell {original_error: stats::mean(original_errors), bias: None}))
+        .collect();
+    if permutation.is_some() {
+        println!();
+        println!("Spatial bias (permuted minus original error)");
+        for (label, original_errors) in original_trials.iter() {
+            let permuted_errors = &permuted_trials[label];
+            let significance = if original_errors.len() > 1 {
+                Significance::paired_t(original_errors, permuted_errors)
+            } else {
+                Significance::mcnemar(&original_outcomes[label], &permuted_outcomes[label])
+            };
+            let report = BiasReport {original_error: stats::mean(original_errors), permuted_error: stats::mean(permuted_errors), significance};
+            println!("{}: {}", label, report);
+            cells.get_mut(label).unwrap().bias = Some(report);
+        }
+    }
+    Ok(cells)
 }
 
-fn load_data_set(file_prefix: &str) -> io::Result<Vec<(u8,Image)>> {
-    let train_images = format!("{}{}-images-idx3-ubyte", BASE_PATH, file_prefix);
-    let train_labels = format!("{}{}-labels-idx1-ubyte", BASE_PATH, file_prefix);
+fn print_trial_summary(title: &str, trial_errors: &BTreeMap<String,Vec<f64>>) {
+    println!("{}", title);
+    for (label, errors) in trial_errors.iter() {
+        if errors.len() == 1 {
+            println!("{}: {}% (deterministic)", label, errors[0]);
+        } else {
+            let (low, high) = stats::confidence_interval_95(errors);
+            println!("{}: mean {}% (stdev {}; 95% CI {}% to {}%; trials: {})", label, stats::mean(errors), stats::stdev(errors), low, high,
+                     errors.iter().map(|e| format!("{}%", e)).collect::<Vec<_>>().join(", "));
+        }
+    }
+}
 
-    let training_images = print_time_milliseconds(&format!("loading mnist {} images", file_prefix),
-        || mnist_data::init_from_files(train_images.as_str(), train_labels.as_str()))?;
+fn export_pgm(args: &Args, permutation_file: &str, dir: &str, training_images: &Vec<(u8,Image)>, testing_images: &Vec<(u8,Image)>, class_names: Option<&[String]>) -> io::Result<()> {
+    print_time_milliseconds(&format!("exporting images to {}", dir), || {
+        netpbm::export_labeled_folders(&format!("{}/original/train", dir), training_images, class_names)?;
+        netpbm::export_labeled_folders(&format!("{}/original/test", dir), testing_images, class_names)?;
+        if args.contains(PERMUTE) {
+            let permutation = read_permutation_for(permutation_file, training_images)?;
+            netpbm::export_labeled_folders(&format!("{}/permuted/train", dir), &permuted_data_set(&permutation, training_images), class_names)?;
+            netpbm::export_labeled_folders(&format!("{}/permuted/test", dir), &permuted_data_set(&permutation, testing_images), class_names)?;
+        }
+        Ok(())
+    })
+}
+
+fn load_data_set(dataset: Dataset, file_prefix: &str, file_names: &[String], cache: &Option<Cache>) -> io::Result<Vec<(u8,Image)>> {
+    let training_images = print_time_milliseconds(&format!("loading {} {} images", dataset, file_prefix), || {
+        match cache {
+            None => dataset.load(file_names),
+            Some(cache) => {
+                let signatures = dataset.source_files(file_names)?.iter().map(|f| file_signature(f)).collect::<io::Result<Vec<String>>>()?;
+                let key = format!("dataset:{}:{}", dataset, signatures.join(","));
+                match cache.load(&key) {
+                    Some(images) => {
+                        println!("Loaded {} {} images from cache", dataset, file_prefix);
+                        Ok(images)
+                    }
+                    None => {
+                        let images = dataset.load(file_names)?;
+                        cache.store(&key, &images);
+                        Ok(images)
+                    }
+                }
+            }
+        }
+    })?;
 
     println!("Number of {} images: {}", file_prefix, training_images.len());
     Ok(training_images)
 }
 
+/// Reads the permutation used by `permute`, which must move every pixel of `images`. The
+/// default file suits 28x28 images only.
+fn read_permutation_for(file_name: &str, images: &[(u8,Image)]) -> io::Result<Vec<usize>> {
+    permutation::read_permutation(file_name, images.first().map_or(0, |(_, img)| img.len()))
+}
+
 fn permuted_data_set(permutation: &Vec<usize>, data: &Vec<(u8,Image)>) -> Vec<(u8,Image)> {
     data.iter()
         .map(|(label, img)| (*label, img.permuted(permutation)))
         .collect()
 }
 
-fn convert_all<I, C: Fn(&Image) -> I>(labeled_list: &Vec<(u8, Image)>, conversion: C) -> Vec<(u8, I)> {
-    labeled_list.iter().map(|(label, img)| (*label, conversion(img))).collect()
+/// Prints and returns the error percentage of each k, when there are any, with the number
+/// of classifications each k rejected under `--reject`.
+fn print_error_rates_by_k(matrices_by_k: &[ConfusionMatrix], reject: Option<f64>) -> Vec<f64> {
+    let error_rates: Vec<f64> = matrices_by_k.iter().map(|matrix| matrix.error_rate() * 100.0).collect();
+    if !error_rates.is_empty() {
+        println!("Error rate by k: {}", error_rates.iter().zip(matrices_by_k.iter()).enumerate()
+            .map(|(i, (e, matrix))| if reject.is_some() {format!("{}: {}% ({} rejected)", i + 1, e, matrix.rejected())} else {format!("{}: {}%", i + 1, e)})
+            .collect::<Vec<_>>().join(", "));
+    }
+    error_rates
+}
+
+/// Prints how many of the classifications recorded in `matrix` were rejected for margins
+/// below `min_margin`, then the coverage and accuracy of rejecting them below each tenth
+/// instead, and returns those with `min_margin`'s, after each of the margins in order.
+/// Without `--reject`, nothing is rejected and there is no curve.
+fn print_coverage(matrix: &ConfusionMatrix, reject: Option<f64>) -> Vec<(f64,f64,f64)> {
+    let min_margin = match reject {
+        Some(min_margin) => min_margin,
+        None => return Vec::new()
+    };
+    println!("Rejected {} of {} images with margins below {}; the error rate covers the rest", matrix.rejected(), matrix.rejected() + matrix.total(), min_margin);
+    let mut min_margins: Vec<f64> = (0..=10).map(|tenths| tenths as f64 / 10.0).collect();
+    print!("{}", matrix.coverage_curve(&min_margins));
+    if !min_margins.contains(&min_margin) {
+        min_margins.push(min_margin);
+        min_margins.sort_by(f64::total_cmp);
+    }
+    min_margins.iter()
+        .map(|min_margin| {
+            let (coverage, accuracy) = matrix.coverage_and_accuracy(*min_margin);
+            (*min_margin, coverage, accuracy)
+        })
+        .collect()
+}
+
+/// The name of `variant`, with its k when other `variants` have the same name.
+fn variant_label(variants: &[Variant], variant: &Variant) -> String {
+    if variants.iter().filter(|other| other.name == variant.name).count() > 1 {
+        format!("{} (k={})", variant.name, variant.k)
+    } else {
+        variant.name.clone()
+    }
 }
 
+fn ks_list(variants: &[Variant]) -> String {
+    variants.iter().map(|variant| variant.k.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn convert_all<I: Send, C: Fn(&Image) -> I + Sync>(labeled_list: &Vec<(u8, Image)>, threads: usize, conversion: C) -> Vec<(u8, I)> {
+    parallel::map(labeled_list, threads, |(label, img)| (*label, conversion(img)))
+}
+
+/// The classifications of one variant's testing images, with their margins under `--reject`.
+type Classified = (Vec<u8>, Vec<f64>);
+
 #[derive(Clone)]
 pub struct ExperimentData {
     training: Vec<(u8,Image)>,
     testing: Vec<(u8,Image)>,
     descriptors: HashMap<String,Descriptor>,
-    errors: BTreeMap<String,f64>
+    errors: BTreeMap<String,f64>,
+    cache: Option<Cache>,
+    folds: Option<(usize,Vec<usize>)>,
+    fold_errors: BTreeMap<String,Vec<f64>>,
+    outcomes: BTreeMap<String,Vec<bool>>,
+    variant_results: Vec<VariantResult>,
+    variants: Vec<Variant>,
+    k_max: Option<usize>,
+    weighting: Weighting,
+    tie_break: TieBreak,
+    reject: Option<f64>,
+    leave_one_out: bool,
+    threads: usize,
+    seed: u64
 }
 
 impl ExperimentData {
-    pub fn build_and_test_model<I: Clone, M: Copy + Eq + Ord, C: Fn(&Image) -> I, D: Fn(&I,&I) -> M>
-    (&mut self, label: &str, conversion: C, distance: D) {
-        self.build_and_test_converting_all(label, |v| convert_all(v, &conversion), distance);
+    pub fn build_and_test_model<I: Clone + Cacheable + Send + Sync, M: Copy + Eq + Ord + Into<f64>, C: Fn(&Image) -> I + Sync, D: Fn(&I,&I) -> M + Sync>
+    (&mut self, variants: &[Variant], cache_key: &str, conversion: C, distance: D, distance_within: BoundedDistance<I,M>, true_distance: fn(f64) -> f64) {
+        let threads = self.threads;
+        self.build_and_test_converting_all(variants, cache_key, |v| convert_all(v, threads, &conversion), distance, distance_within, true_distance);
     }
 
-    pub fn build_and_test_converting_all<I: Clone, M: Copy + Eq + Ord, C: Fn(&Vec<(u8,Image)>) -> Vec<(u8,I)>, D: Fn(&I,&I) -> M>
-    (&mut self, label: &str, conversion: C, distance: D) {
-        let training_images = print_time_milliseconds(&format!("converting training images to {}", label),
-                                                      || conversion(&self.training));
+    /// `variants` share a name and differ only in k, so they are tested with the same
+    /// converted images and neighbor search. `cache_key` must identify the conversion,
+    /// including any randomly generated parameters. `distance_within` is the
+    /// early-abandoning version of `distance` used by the neighbor search, and
+    /// `true_distance` converts its values to the distances that weigh the votes.
+    pub fn build_and_test_converting_all<I: Clone + Cacheable + Sync, M: Copy + Eq + Ord + Into<f64>, C: Fn(&Vec<(u8,Image)>) -> Vec<(u8,I)>, D: Fn(&I,&I) -> M + Sync>
+    (&mut self, variants: &[Variant], cache_key: &str, conversion: C, distance: D, distance_within: BoundedDistance<I,M>, true_distance: fn(f64) -> f64) {
+        let label = variants[0].name.as_str();
+        let (training_images, training_conversion_ms) = print_and_time_milliseconds(&format!("converting training images to {}", label),
+                                                                                   || self.convert_cached(cache_key, &self.training, &conversion));
 
-        let testing_images = print_time_milliseconds(&format!("converting testing images to {}", label),
-                                                     || conversion(&self.testing));
+        if self.folds.is_some() {
+            self.cross_validate(variants, &training_images, distance, distance_within, true_distance, training_conversion_ms);
+            return;
+        }
+        if self.leave_one_out {
+            self.test_leaving_one_out(variants, &training_images, distance, distance_within, true_distance, training_conversion_ms);
+            return;
+        }
+
+        let (testing_images, testing_conversion_ms) = print_and_time_milliseconds(&format!("converting testing images to {}", label),
+                                                                                 || self.convert_cached(cache_key, &self.testing, &conversion));
 
-        let mut model = knn::Knn::new(K, distance);
-        print_time_milliseconds(&format!("training {} model (k={})", label, K),
-                                || model.train(&training_images));
-        let outcome = print_time_milliseconds("testing", || model.test(&testing_images));
-        print!("{}", outcome);
-        let error_percentage = outcome.error_rate() * 100.0;
-        println!("Error rate: {}", error_percentage);
-        self.errors.insert(label.to_string(), error_percentage);
+        let mut model = self.knn_model(variants, distance, distance_within, true_distance);
+        let ((), training_ms) = print_and_time_milliseconds(&format!("training {} model (k={})", label, ks_list(variants)),
+                                                            || model.train(&training_images));
+        let (classified, testing_ms) = print_and_time_milliseconds("testing", || self.classify_with(variants, &testing_images,
+            || model.classify_all(&testing_images), || model.classify_all_with_margins(&testing_images), |k_max| model.classify_all_for_each_k(&testing_images, k_max)));
+        self.record_test(variants, training_images.len(), &testing_images, classified, training_conversion_ms + testing_conversion_ms, (training_ms, testing_ms));
     }
 
-    pub fn get_descriptor(&self, name: &str) -> Descriptor {
-        self.descriptors.get(name).unwrap().clone()
+    /// Builds the untrained model for `variants`, with the k of the first and the run's
+    /// weighting, tie break and threads, for every way of testing it.
+    fn knn_model<I, M, D: Fn(&I,&I) -> M>(&self, variants: &[Variant], distance: D, distance_within: BoundedDistance<I,M>, true_distance: fn(f64) -> f64) -> knn::Knn<I, M, D> {
+        knn::Knn::new(variants[0].k, distance).with_distance_within(distance_within).with_true_distance(true_distance)
+            .with_weighting(self.weighting).with_tie_break(self.tie_break).with_threads(self.threads)
+    }
+
+    /// Tests each training image against all of the others, in place of the testing images.
+    fn test_leaving_one_out<I: Clone + Sync, M: Copy + Eq + Ord + Into<f64>, D: Fn(&I,&I) -> M + Sync>(&mut self, variants: &[Variant], training_images: &Vec<(u8,I)>, distance: D, distance_within: BoundedDistance<I,M>, true_distance: fn(f64) -> f64, conversion_ms: u128) {
+        let mut model = self.knn_model(variants, distance, distance_within, true_distance);
+        let ((), training_ms) = print_and_time_milliseconds(&format!("training {} model (k={})", variants[0].name, ks_list(variants)),
+                                                            || model.train(training_images));
+        let (classified, testing_ms) = print_and_time_milliseconds("leave-one-out testing", || self.classify_with(variants, training_images,
+            || model.leave_one_out(), || model.leave_one_out_with_margins(), |k_max| model.leave_one_out_for_each_k(k_max)));
+        self.record_test(variants, training_images.len(), training_images, classified, conversion_ms, (training_ms, testing_ms));
+    }
+
+    /// Prints and records the results of classifying `testing_images` for each of `variants`,
+    /// given their classifications and margins, and the confusion matrices of each k, from
+    /// `classify_with`.
+    fn record_test<I>(&mut self, variants: &[Variant], training_count: usize, testing_images: &[(u8,I)], (classified, matrices_by_k): (Vec<Classified>, Vec<ConfusionMatrix>),
+                      conversion_ms: u128, (training_ms, testing_ms): (u128, u128)) {
+        let error_rates_by_k = print_error_rates_by_k(&matrices_by_k, self.reject);
+        for (variant, (classifications, margins)) in variants.iter().zip(classified.iter()) {
+            let label = variant_label(&self.variants, variant);
+            if variants.len() > 1 {
+                println!("{}", label);
+            }
+            let outcome = self.record_outcomes(&label, testing_images, classifications, margins);
+            print!("{}", outcome);
+            print!("{}", outcome.grid());
+            print!("{}", outcome.metrics());
+            let error_percentage = outcome.error_rate() * 100.0;
+            println!("Error rate: {}", error_percentage);
+            let coverage_curve = print_coverage(&outcome, self.reject);
+            self.errors.insert(label, error_percentage);
+            self.variant_results.push(VariantResult {
+                variant: variant.name.clone(),
+                settings: variant.to_string(),
+                training_images: training_count,
+                testing_images: testing_images.len(),
+                error_rate: error_percentage,
+                fold_error_rates: Vec::new(),
+                error_rates_by_k: error_rates_by_k.clone(),
+                coverage_curve,
+                confusion: outcome,
+                conversion_ms,
+                training_ms,
+                testing_ms
+            });
+        }
     }
 
-    pub fn add_descriptor(&mut self, name: &str, d: Descriptor) {
-        self.descriptors.insert(name.to_string(), d);
+    /// The classifications of `labeled` for each of `variants`, with their margins under
+    /// `--reject`, and the confusion matrix of every k up to `--k-max`. A single variant
+    /// without `--k-max` uses `classify`, or `classify_with_margins` with `--reject`.
+    /// Otherwise the same search for nearest neighbors, by `classify_for_each_k`, gives the
+    /// classifications and margins of every variant's k and of every k up to `--k-max`.
+    fn classify_with<I, C: FnOnce() -> Vec<u8>, R: FnOnce() -> Vec<(u8, f64)>, E: FnOnce(usize) -> Vec<Vec<(u8, f64)>>>
+    (&self, variants: &[Variant], labeled: &[(u8,I)], classify: C, classify_with_margins: R, classify_for_each_k: E) -> (Vec<Classified>, Vec<ConfusionMatrix>) {
+        match (self.reject, self.k_max) {
+            (Some(_), None) if variants.len() == 1 => (vec![classify_with_margins().into_iter().unzip()], Vec::new()),
+            (None, None) if variants.len() == 1 => (vec![(classify(), Vec::new())], Vec::new()),
+            (reject, k_max) => {
+                let k_search = variants.iter().map(|variant| variant.k).chain(k_max).max().unwrap();
+                let by_k = classify_for_each_k(k_search);
+                let classified = variants.iter()
+                    .map(|variant| {
+                        let (classifications, margins): (Vec<u8>, Vec<f64>) = by_k.iter().map(|classifications| classifications[variant.k - 1]).unzip();
+                        (classifications, if reject.is_some() {margins} else {Vec::new()})
+                    })
+                    .collect();
+                let matrices_by_k = if k_max.is_some() {knn::confusion_for_each_k(labeled, &by_k, k_search, reject)} else {Vec::new()};
+                (classified, matrices_by_k)
+            }
+        }
     }
 
-    pub fn run_all_tests_with(&mut self, args: &HashSet<String>) {
-        if args.contains(BASELINE) {
-            self.build_and_test_model(BASELINE, |v| v.clone(), euclidean_distance::euclidean_distance);
+    /// Appends whether each image was classified correctly to the outcomes for `label`.
+    /// With `--reject`, the classifications with `margins` below it are rejected: they are
+    /// left out of the confusion matrix but for its rejected count, and are not correct.
+    fn record_outcomes<I>(&mut self, label: &str, testing_images: &[(u8,I)], classifications: &[u8], margins: &[f64]) -> ConfusionMatrix {
+        let mut outcome = ConfusionMatrix::new();
+        let outcomes = self.outcomes.entry(label.to_string()).or_default();
+        for (i, ((img_label, _), classification)) in testing_images.iter().zip(classifications.iter()).enumerate() {
+            match (margins.get(i), self.reject) {
+                (Some(margin), Some(min_margin)) => {
+                    outcome.record_or_reject(*img_label, *classification, *margin, min_margin);
+                    outcomes.push(reject_below((*classification, *margin), min_margin) == Some(*img_label));
+                }
+                _ => {
+                    outcome.record(*img_label, *classification);
+                    outcomes.push(img_label == classification);
+                }
+            }
         }
-        if args.contains(BRIEF) {
-            self.build_and_test_descriptor(BRIEF);
+        outcome
+    }
+
+    fn cross_validate<I: Clone + Sync, M: Copy + Eq + Ord + Into<f64>, D: Fn(&I,&I) -> M + Sync>(&mut self, variants: &[Variant], training_images: &[(u8,I)], distance: D, distance_within: BoundedDistance<I,M>, true_distance: fn(f64) -> f64, conversion_ms: u128) {
+        let labels: Vec<String> = variants.iter().map(|variant| variant_label(&self.variants, variant)).collect();
+        let (num_folds, folds) = self.folds.clone().unwrap();
+        let mut confusions = vec![ConfusionMatrix::new(); variants.len()];
+        let mut fold_errors: Vec<Vec<f64>> = vec![Vec::new(); variants.len()];
+        let mut matrices_by_k: Vec<ConfusionMatrix> = Vec::new();
+        let mut training_ms = 0;
+        let mut testing_ms = 0;
+        for fold in 0..num_folds {
+            let (fold_training, fold_testing) = sampling::split_by_fold(training_images, &folds, fold);
+            let mut model = self.knn_model(variants, &distance, distance_within, true_distance);
+            training_ms += time_milliseconds(|| model.train(&fold_training)).1;
+            let ((classified, fold_matrices_by_k), fold_testing_ms) = print_and_time_milliseconds(&format!("testing fold {} of {}", fold + 1, num_folds),
+                || self.classify_with(variants, &fold_testing, || model.classify_all(&fold_testing), || model.classify_all_with_margins(&fold_testing),
+                                      |k_max| model.classify_all_for_each_k(&fold_testing, k_max)));
+            testing_ms += fold_testing_ms;
+            if matrices_by_k.is_empty() {
+                matrices_by_k = fold_matrices_by_k;
+            } else {
+                matrices_by_k.iter_mut().zip(fold_matrices_by_k.iter()).for_each(|(matrix, fold_matrix)| matrix.merge(fold_matrix));
+            }
+            for (i, (classifications, margins)) in classified.iter().enumerate() {
+                let outcome = self.record_outcomes(&labels[i], &fold_testing, classifications, margins);
+                confusions[i].merge(&outcome);
+                fold_errors[i].push(outcome.error_rate() * 100.0);
+            }
         }
-        if args.contains(UNIFORM_BRIEF) {
-            self.build_and_test_descriptor(UNIFORM_BRIEF);
+        let error_rates_by_k = print_error_rates_by_k(&matrices_by_k, self.reject);
+        for (((variant, label), confusion), fold_errors) in variants.iter().zip(labels).zip(confusions).zip(fold_errors) {
+            if variants.len() > 1 {
+                println!("{}", label);
+            }
+            println!("Error rate: {} (stdev {})", stats::mean(&fold_errors), stats::stdev(&fold_errors));
+            let coverage_curve = print_coverage(&confusion, self.reject);
+            self.errors.insert(label.clone(), stats::mean(&fold_errors));
+            self.variant_results.push(VariantResult {
+                variant: variant.name.clone(),
+                settings: variant.to_string(),
+                training_images: training_images.len(),
+                testing_images: training_images.len(),
+                error_rate: stats::mean(&fold_errors),
+                fold_error_rates: fold_errors.clone(),
+                error_rates_by_k: error_rates_by_k.clone(),
+                coverage_curve,
+                confusion,
+                conversion_ms,
+                training_ms,
+                testing_ms
+            });
+            self.fold_errors.insert(label, fold_errors);
         }
-        if args.contains(UNIFORM_NEIGHBORS) {
-            self.build_and_test_descriptor(UNIFORM_NEIGHBORS);
+    }
+
+    fn convert_cached<I: Cacheable, C: Fn(&Vec<(u8,Image)>) -> Vec<(u8,I)>>
+    (&self, cache_key: &str, images: &Vec<(u8,Image)>, conversion: &C) -> Vec<(u8,I)> {
+        match &self.cache {
+            None => conversion(images),
+            Some(cache) => cache.load_or_else(&format!("{}:{:016x}", cache_key, fingerprint(images)), || conversion(images))
         }
-        if args.contains(GAUSSIAN_NEIGHBORS) {
-            self.build_and_test_descriptor(GAUSSIAN_NEIGHBORS);
+    }
+
+    /// Clears the errors of any previous trial and generates new descriptors from `seed`.
+    pub fn start_trial(&mut self, seed: u64) {
+        self.seed = seed;
+        self.errors.clear();
+        self.fold_errors.clear();
+        self.outcomes.clear();
+        self.variant_results.clear();
+
+        self.descriptors.clear();
+        let (width, height) = self.image_dimensions();
+        for variant in self.variants.clone() {
+            if variant.features.is_descriptor() && !self.descriptors.contains_key(&variant.name) {
+                self.add_descriptor(&variant.name, &format!("{}x{}:{}", width, height, variant.features),
+                                    |rng| variant.features.make_descriptor(width, height, rng));
+            }
         }
-        if args.contains(GAUSSIAN_7) {
-            self.build_and_test_descriptor(GAUSSIAN_7);
+    }
+
+    pub fn record_errors(&self, trial_errors: &mut BTreeMap<String,Vec<f64>>) {
+        for (label, error) in self.errors.iter() {
+            trial_errors.entry(label.clone()).or_default().push(*error);
         }
-        if args.contains(PATCH) {
-            self.build_and_test_patch(PATCH, PATCH_SIZE);
+    }
+
+    pub fn image_dimensions(&self) -> (usize, usize) {
+        let img = &self.training[0].1;
+        (img.width(), img.height())
+    }
+
+    pub fn get_descriptor(&self, name: &str) -> Descriptor {
+        self.descriptors.get(name).unwrap().clone()
+    }
+
+    /// `make` is given a generator seeded from the experiment seed and `name`. With a cache,
+    /// descriptors are reused across runs with the same name, `parameters` and seed.
+    pub fn add_descriptor<F: FnOnce(&mut StdRng) -> Descriptor>(&mut self, name: &str, parameters: &str, make: F) {
+        let mut rng = seeds::rng_for(self.seed, name);
+        let descriptor = match &self.cache {
+            Some(cache) => cache.load_or_else(&format!("descriptor:{}:{}:{}", name, parameters, self.seed), || make(&mut rng)),
+            None => make(&mut rng)
+        };
+        self.descriptors.insert(name.to_string(), descriptor);
+    }
+
+    /// Variants without random features are skipped unless `include_deterministic`, as
+    /// another trial would only repeat their results. Variants with the same name, which
+    /// differ only in k, are tested together.
+    pub fn run_all_tests(&mut self, include_deterministic: bool) {
+        let mut names: Vec<String> = Vec::new();
+        for variant in self.variants.iter() {
+            if !names.contains(&variant.name) {
+                names.push(variant.name.clone());
+            }
         }
-        if args.contains(CONVOLUTIONAL_1) {
-            self.build_and_test_converting_all(CONVOLUTIONAL_1, |images| kernelize_all(images, 1), kernelized_distance);
+        for name in names {
+            let variants: Vec<Variant> = self.variants.iter().filter(|variant| variant.name == name).cloned().collect();
+            if include_deterministic || variants[0].features.is_random() {
+                self.build_and_test_variant(&variants);
+            }
         }
     }
 
-    fn build_and_test_descriptor(&mut self, descriptor_name: &str) {
-        let descriptor = self.get_descriptor(descriptor_name);
-        self.build_and_test_model(descriptor_name, |img| descriptor.apply_to(img), bits::distance);
+    fn build_and_test_variant(&mut self, variants: &[Variant]) {
+        let variant = &variants[0];
+        match (variant.features, variant.distance) {
+            (Features::Raw, Distance::Manhattan) =>
+                self.build_and_test_model(variants, "raw:manhattan", |img| img.clone(), euclidean_distance::manhattan_distance, euclidean_distance::manhattan_distance_within, |d| d),
+            (Features::Raw, _) =>
+                self.build_and_test_model(variants, "raw", |img| img.clone(), euclidean_distance::euclidean_distance, euclidean_distance::euclidean_distance_within, f64::sqrt),
+            (Features::Patch {size}, _) =>
+                self.build_and_test_model(variants, &format!("patch:{}", size), |img| patchify(img, size), bits::distance, bits::distance_within, |d| d),
+            (Features::Convolutional {levels}, _) => {
+                let (seed, threads) = (self.seed, self.threads);
+                let label = variant.name.as_str();
+                self.build_and_test_converting_all(variants, &format!("convolutional:{}:{}:{}", label, levels, seed),
+                                                   |images| kernelize_all(images, levels, threads, &mut seeds::rng_for(seed, label)), kernelized_distance, kernelized_distance_within, f64::sqrt);
+            }
+            _ => self.build_and_test_descriptor(variants)
+        }
     }
 
-    fn build_and_test_patch(&mut self, label: &str, patch_size: usize) {
-        self.build_and_test_model(label, |img| patchify(img, patch_size), bits::distance);
+    fn build_and_test_descriptor(&mut self, variants: &[Variant]) {
+        let descriptor = self.get_descriptor(&variants[0].name);
+        let cache_key = format!("{}:{:016x}", variants[0].name, fingerprint(&descriptor));
+        self.build_and_test_model(variants, &cache_key, |img| descriptor.apply_to(img), bits::distance, bits::distance_within, |d| d);
     }
 
     pub fn permuted(&self, permutation: &Vec<usize>) -> ExperimentData {
@@ -230,13 +1170,30 @@ impl ExperimentData {
             training: permuted_data_set(permutation, &self.training),
             testing: permuted_data_set(permutation, &self.testing),
             descriptors: self.descriptors.clone(),
-            errors: BTreeMap::new()
+            errors: BTreeMap::new(),
+            cache: self.cache.clone(),
+            folds: self.folds.clone(),
+            fold_errors: BTreeMap::new(),
+            outcomes: BTreeMap::new(),
+            variant_results: Vec::new(),
+            variants: self.variants.clone(),
+            k_max: self.k_max,
+            weighting: self.weighting,
+            tie_break: self.tie_break,
+            reject: self.reject,
+            leave_one_out: self.leave_one_out,
+            threads: self.threads,
+            seed: self.seed
         }
     }
 
     pub fn print_errors(&self) {
         for (k,v) in self.errors.iter() {
-            println!("{}: {}%", k, v);
+            match self.fold_errors.get(k) {
+                Some(fold_errors) => println!("{}: {}% (stdev {}; folds: {})", k, v, stats::stdev(fold_errors),
+                                              fold_errors.iter().map(|e| format!("{}%", e)).collect::<Vec<_>>().join(", ")),
+                None => println!("{}: {}%", k, v)
+            }
         }
     }
 }
\ No newline at end of file
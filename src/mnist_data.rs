@@ -1,25 +1,26 @@
-use std::fs;
 use std::io;
-use std::io::Read;
 use std::ops::{AddAssign, Add};
+use crate::idx::{IdxData, read_idx_file, invalid_data};
 
-pub const IMAGE_DIMENSION: usize = 28;
-pub const IMAGE_BYTES: usize = IMAGE_DIMENSION * IMAGE_DIMENSION;
-
-#[derive(Clone, Debug, Default)]
+/// An image of one or more planes of equal height, stacked top to bottom.
+/// Windows around a pixel never reach into the planes above or below it.
+#[derive(Clone, Debug)]
 pub struct Image {
     pixels: Vec<u8>,
-    side_size: usize,
+    width: usize,
+    height: usize,
+    planes: usize,
 }
 
 pub trait Grid<T> {
     fn add(&mut self, pixel: T);
     fn get(&self, x: usize, y: usize) -> T;
-    fn side(&self) -> usize;
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
     fn len(&self) -> usize;
 
     fn in_bounds(&self, x: isize, y: isize) -> bool {
-        x >= 0 && y >= 0 && x < self.side() as isize && y < self.side() as isize
+        x >= 0 && y >= 0 && x < self.width() as isize && y < self.height() as isize
     }
 
     fn option_get(&self, x: isize, y: isize) -> Option<T> {
@@ -27,29 +28,28 @@ pub trait Grid<T> {
     }
 
     fn x_y_iter(&self) -> ImageIterator<usize> {
-        ImageIterator::new(0, 0, self.side(), self.side(), 1)
-    }
-
-    fn x_y_step_iter(&self, step_size: usize) -> ImageIterator<usize> {
-        ImageIterator::new(0, 0, self.side(), self.side(), step_size)
+        ImageIterator::new(0, 0, self.width(), self.height(), 1)
     }
 }
 
 impl Grid<u8> for Image {
     fn add(&mut self, pixel: u8) {
+        assert!(self.width > 0);
         self.pixels.push(pixel);
-        if self.pixels.len() > self.side_size.pow(2) {
-            self.side_size += 1;
-        }
+        self.height = self.pixels.len().div_ceil(self.width);
     }
 
     fn get(&self, x: usize, y: usize) -> u8 {
         assert!(self.in_bounds(x as isize, y as isize));
-        self.pixels[y * self.side_size + x]
+        self.pixels[y * self.width + x]
     }
 
-    fn side(&self) -> usize {
-        self.side_size
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
     }
 
     fn len(&self) -> usize {
@@ -58,39 +58,90 @@ impl Grid<u8> for Image {
 }
 
 impl Image {
-    pub fn new() -> Self {
-        Default::default()
+    pub fn with_width(width: usize) -> Self {
+        Image {pixels: Vec::new(), width, height: 0, planes: 1}
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<u8>) -> Image {
+        assert_eq!(width * height, pixels.len());
+        Image {pixels, width, height, planes: 1}
+    }
+
+    /// Stacks same-sized single-plane images, such as color channels, into one image.
+    #[cfg(test)]
+    pub fn stacked(planes: &[Image]) -> Image {
+        assert!(!planes.is_empty());
+        assert!(planes.iter().all(|plane| plane.planes == 1 && plane.same_size(&planes[0])));
+        let pixels = planes.iter().flat_map(|plane| plane.pixels.iter().copied()).collect();
+        Image::from_pixels(planes[0].width, planes.len() * planes[0].height, pixels).with_planes(planes.len())
+    }
+
+    pub fn with_planes(mut self, planes: usize) -> Image {
+        assert!(planes > 0 && self.height.is_multiple_of(planes));
+        self.planes = planes;
+        self
+    }
+
+    pub fn planes(&self) -> usize {
+        self.planes
+    }
+
+    pub fn plane_height(&self) -> usize {
+        self.height / self.planes
+    }
+
+    /// `option_get`, except that pixels outside the plane containing row `y_center` are `None`.
+    pub fn option_get_in_plane(&self, y_center: usize, x: isize, y: isize) -> Option<u8> {
+        let plane_height = self.plane_height() as isize;
+        let top = y_center as isize / plane_height * plane_height;
+        if y >= top && y < top + plane_height { self.option_get(x, y) } else { None }
+    }
+
+    /// Visits every `step_size`th pixel in each direction, restarting at the top of each plane.
+    pub fn plane_step_iter(&self, step_size: usize) -> impl Iterator<Item=(usize,usize)> {
+        let (width, plane_height) = (self.width, self.plane_height());
+        (0..self.planes).flat_map(move |p| ImageIterator::new(0, p * plane_height, width, plane_height, step_size))
     }
 
     #[cfg(test)]
     pub fn from_vec(v: &Vec<u8>) -> Image {
-        let mut result = Image::new();
+        let mut side = 0;
+        while side * side < v.len() {
+            side += 1;
+        }
+        let mut result = Image::with_width(side);
         v.iter().for_each(|p| result.add(*p));
         result
     }
 
+    pub fn same_size(&self, other: &Image) -> bool {
+        self.width == other.width && self.height == other.height
+    }
+
+    pub fn transposed(&self) -> Image {
+        let mut result = Image::with_width(self.height);
+        ImageIterator::new(0, 0, self.height, self.width, 1)
+            .for_each(|(x, y)| result.add(self.get(y, x)));
+        result
+    }
+
     pub fn permuted(&self, permutation: &[usize]) -> Image {
         assert_eq!(self.pixels.len(), permutation.len());
-        let mut result = Image::new();
-        for index in permutation {
-            result.add(self.pixels[*index]);
-        }
-        result
+        Image::from_pixels(self.width, self.height, permutation.iter().map(|i| self.pixels[*i]).collect()).with_planes(self.planes)
     }
 
     pub fn shrunken(&self, shrink: usize) -> Image {
-        let mut result = Image::new();
-        let target_side = self.side() / shrink;
-        ImageIterator::new(0, 0, target_side, target_side, 1)
-            .for_each(|(x, y)| result.add(self.subimage_mean(x, y, shrink)));
+        let mut result = Image::with_width(self.width / shrink);
+        ImageIterator::new(0, 0, self.width / shrink, self.height / shrink, 1)
+            .for_each(|(x, y)| result.add(self.subimage_mean(x * shrink, y * shrink, shrink)));
         result
     }
 
     pub fn subimage(&self, x_center: usize, y_center: usize, side: usize) -> Image {
-        let mut result = Image::new();
+        let mut result = Image::with_width(side);
         ImageIterator::centered(x_center as isize, y_center as isize, side as isize, side as isize, 1)
-            .for_each(|(x, y)| result.add(self.option_get(x, y).unwrap_or(0)));
-        assert_eq!(side, result.side());
+            .for_each(|(x, y)| result.add(self.option_get_in_plane(y_center, x, y).unwrap_or(0)));
+        assert_eq!(side, result.height());
         result
     }
 
@@ -114,7 +165,7 @@ impl Image {
 
 impl PartialEq for Image {
     fn eq(&self, other: &Self) -> bool {
-        self.side_size == other.side_size && self.pixels.len() == other.pixels.len() && (0..self.pixels.len()).all(|i| self.pixels[i] == other.pixels[i])
+        self.same_size(other) && self.planes == other.planes && self.pixels.len() == other.pixels.len() && (0..self.pixels.len()).all(|i| self.pixels[i] == other.pixels[i])
     }
 }
 
@@ -122,7 +173,7 @@ impl Eq for Image {}
 
 pub fn image_mean(images: &Vec<Image>) -> Image {
     assert!(!images.is_empty());
-    assert!(images.iter().all(|img| img.pixels.len() == images[0].pixels.len()));
+    assert!(images.iter().all(|img| img.same_size(&images[0])));
     let mut sums: Vec<usize> = (0..images[0].pixels.len()).map(|_| 0).collect();
     for image in images.iter() {
         for p in 0..image.pixels.len() {
@@ -130,11 +181,8 @@ pub fn image_mean(images: &Vec<Image>) -> Image {
         }
     }
 
-    let mut result = Image::new();
-    for sum in sums {
-        result.add((sum / images.len()) as u8);
-    }
-    result
+    Image::from_pixels(images[0].width, images[0].height, sums.iter().map(|sum| (sum / images.len()) as u8).collect())
+        .with_planes(images[0].planes)
 }
 
 pub struct ImageIterator<N> {
@@ -178,8 +226,13 @@ impl<N: Copy + AddAssign + Add<Output=N> + Eq + Ord> Iterator for ImageIterator<
 }
 
 pub fn init_from_files(image_file_name: &str, label_file_name: &str) -> io::Result<Vec<(u8,Image)>> {
-    let bytes: Vec<u8> = read_label_file(label_file_name)?;
-    read_image_file(image_file_name, &bytes)
+    let labels = read_label_file(label_file_name)?;
+    let images = read_image_file(image_file_name)?;
+    if labels.len() != images.len() {
+        return Err(invalid_data(format!("{} contains {} labels, but {} contains {} images",
+                                        label_file_name, labels.len(), image_file_name, images.len())));
+    }
+    Ok(labels.into_iter().zip(images).collect())
 }
 
 pub fn discard(items: &[(u8,Image)], shrink: usize) -> Vec<(u8,Image)> {
@@ -193,64 +246,82 @@ pub fn discard(items: &[(u8,Image)], shrink: usize) -> Vec<(u8,Image)> {
 }
 
 fn read_label_file(label_file_name: &str) -> io::Result<Vec<u8>> {
-    let fin = fs::File::open(label_file_name)?;
-    let mut bytes: Vec<u8> = Vec::new();
-    let mut header_bytes_left = 8;
-    for b in fin.bytes() {
-        if header_bytes_left > 0 {
-            header_bytes_left -= 1;
-        } else {
-            let b = b?;
-            bytes.push(b);
-        }
+    let idx = read_idx_file(label_file_name)?;
+    if idx.dimensions().len() != 1 {
+        return Err(invalid_data(format!("{}: label files must have 1 dimension, found {:?}", label_file_name, idx.dimensions())));
     }
-    Ok(bytes)
+    Ok(idx.move_data())
 }
 
-fn read_image_file(image_file_name: &str, labels: &[u8]) -> io::Result<Vec<(u8,Image)>> {
-    let fin = fs::File::open(image_file_name)?;
-    let mut images: Vec<(u8,Image)> = Vec::new();
-    let mut image: Image = Image::new();
-    let mut header_bytes_left = 16;
-    for b in fin.bytes() {
-        if header_bytes_left > 0 {
-            header_bytes_left -= 1;
-        } else {
-            image.add(b?);
-            if image.len() == IMAGE_BYTES {
-                images.push((labels[images.len()], image));
-                image = Image::new();
-            }
-        }
+fn read_image_file(image_file_name: &str) -> io::Result<Vec<Image>> {
+    let idx = read_idx_file(image_file_name)?;
+    images_from_idx(&idx).map_err(|e| invalid_data(format!("{}: {}", image_file_name, e)))
+}
+
+fn images_from_idx(idx: &IdxData) -> io::Result<Vec<Image>> {
+    let dims = idx.dimensions();
+    if dims.len() != 3 {
+        return Err(invalid_data(format!("image files must have 3 dimensions (count, rows, columns), found {:?}", dims)));
+    }
+    let (height, width) = (dims[1], dims[2]);
+    if height == 0 || width == 0 {
+        return Err(invalid_data(format!("images must have at least one row and column, found {}x{}", width, height)));
     }
-    Ok(images)
+    Ok(idx.items()
+        .map(|pixels| Image::from_pixels(width, height, pixels.to_vec()))
+        .collect())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::idx::{parse_idx, encode_idx};
 
     #[test]
     fn test_img() {
-        let mut img = Image::new();
-        assert_eq!(0, img.side());
+        let mut img = Image::with_width(3);
+        assert_eq!(3, img.width());
+        assert_eq!(0, img.height());
         img.add(10);
-        assert_eq!(1, img.side());
+        assert_eq!(1, img.height());
         assert_eq!(10, img.get(0, 0));
         img.add(20);
-        assert_eq!(2, img.side());
-        assert_eq!(20, img.get(1, 0));
         img.add(30);
-        assert_eq!(2, img.side());
-        assert_eq!(30, img.get(0, 1));
-        img.add(40);
-        assert_eq!(2, img.side());
-        assert_eq!(40, img.get(1, 1));
-        img.add(50);
-        assert_eq!(3, img.side());
+        assert_eq!(1, img.height());
         assert_eq!(30, img.get(2, 0));
+        img.add(40);
+        assert_eq!(2, img.height());
         assert_eq!(40, img.get(0, 1));
-        assert_eq!(50, img.get(1, 1));
+        img.add(50);
+        img.add(60);
+        assert_eq!(2, img.height());
+        assert_eq!(60, img.get(2, 1));
+        assert!(!img.in_bounds(3, 0));
+        assert!(!img.in_bounds(0, 2));
+        assert_eq!(img, Image::from_pixels(3, 2, vec![10, 20, 30, 40, 50, 60]));
+        assert_ne!(img, Image::from_pixels(2, 3, vec![10, 20, 30, 40, 50, 60]));
+    }
+
+    #[test]
+    fn test_rectangular() {
+        let img = Image::from_pixels(4, 2, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        let xys: Vec<(usize,usize)> = img.x_y_iter().collect();
+        assert_eq!(8, xys.len());
+        assert_eq!((3, 1), xys[7]);
+        assert_eq!(7, img.get(2, 1));
+
+        let shrunk = Image::from_pixels(4, 2, vec![2, 4, 6, 8, 2, 4, 6, 8]).shrunken(2);
+        assert_eq!(Image::from_pixels(2, 1, vec![3, 7]), shrunk);
+
+        let sub = img.subimage(3, 1, 3);
+        assert_eq!(Image::from_pixels(3, 3, vec![3, 4, 0, 7, 8, 0, 0, 0, 0]), sub);
+
+        let transposed = img.transposed();
+        assert_eq!(Image::from_pixels(2, 4, vec![1, 5, 2, 6, 3, 7, 4, 8]), transposed);
+        assert_eq!(img, transposed.transposed());
+
+        let permuted = img.permuted(&[7, 6, 5, 4, 3, 2, 1, 0]);
+        assert_eq!(Image::from_pixels(4, 2, vec![8, 7, 6, 5, 4, 3, 2, 1]), permuted);
     }
 
     #[test]
@@ -280,4 +351,37 @@ mod tests {
         let ref_sub = Image::from_vec(&vec![1, 2, 3, 5, 6, 7, 9, 10, 11]);
         assert_eq!(ref_sub, sub);
     }
+
+    #[test]
+    fn test_planes() {
+        let red = Image::from_pixels(3, 2, vec![1, 2, 3, 4, 5, 6]);
+        let green = Image::from_pixels(3, 2, vec![7, 8, 9, 10, 11, 12]);
+        let img = Image::stacked(&[red, green]);
+        assert_eq!(4, img.height());
+        assert_eq!(2, img.plane_height());
+        assert_eq!(7, img.get(0, 2));
+        assert_eq!(Some(4), img.option_get_in_plane(1, 0, 1));
+        assert_eq!(None, img.option_get_in_plane(1, 0, 2));
+        assert_eq!(Image::from_pixels(3, 3, vec![0, 0, 0, 7, 8, 9, 10, 11, 12]), img.subimage(1, 2, 3));
+        let starts: Vec<(usize,usize)> = img.plane_step_iter(2).collect();
+        assert_eq!(vec![(0, 0), (2, 0), (0, 2), (2, 2)], starts);
+    }
+
+    #[test]
+    fn test_images_from_idx() {
+        let idx = parse_idx(&encode_idx(&[2, 2, 2], &[1, 2, 3, 4, 5, 6, 7, 8])).unwrap();
+        let images = images_from_idx(&idx).unwrap();
+        assert_eq!(vec![Image::from_vec(&vec![1, 2, 3, 4]), Image::from_vec(&vec![5, 6, 7, 8])], images);
+
+        let wide = parse_idx(&encode_idx(&[1, 2, 3], &[1, 2, 3, 4, 5, 6])).unwrap();
+        let images = images_from_idx(&wide).unwrap();
+        assert_eq!(3, images[0].width());
+        assert_eq!(2, images[0].height());
+
+        let labels = parse_idx(&encode_idx(&[2, 2], &[1, 2, 3, 4])).unwrap();
+        assert!(images_from_idx(&labels).is_err());
+
+        let empty = parse_idx(&encode_idx(&[2, 0, 3], &[])).unwrap();
+        assert!(images_from_idx(&empty).is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,235 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use crate::mnist_data::{Image, Grid};
+use crate::cifar::{ColorMode, luminance};
+use crate::idx::invalid_data;
+
+const EXTENSIONS: [&str; 3] = ["pgm", "ppm", "pnm"];
+
+/// Reads a P2, P3, P5 or P6 file. Color images are converted according to `mode`, and
+/// sample values are rescaled to 0..=255 when the file's maximum value differs.
+pub fn read_pnm(file_name: &str, mode: ColorMode) -> io::Result<Image> {
+    let bytes = fs::read(file_name)?;
+    parse_pnm(&bytes, mode).map_err(|e| invalid_data(format!("{}: {}", file_name, e)))
+}
+
+pub fn parse_pnm(bytes: &[u8], mode: ColorMode) -> io::Result<Image> {
+    let mut header = HeaderReader {bytes, pos: 0};
+    let magic = header.token()?;
+    let (channels, binary) = match magic.as_str() {
+        "P2" => (1, false),
+        "P3" => (3, false),
+        "P5" => (1, true),
+        "P6" => (3, true),
+        _ => return Err(invalid_data(format!("unsupported netpbm magic number \"{}\"", magic)))
+    };
+    let width = header.number()?;
+    let height = header.number()?;
+    let max_value = header.number()?;
+    if max_value == 0 || max_value > u16::MAX as usize {
+        return Err(invalid_data(format!("invalid maximum value {}", max_value)));
+    }
+
+    let num_samples = width.checked_mul(height).and_then(|n| n.checked_mul(channels))
+        .ok_or_else(|| invalid_data(format!("{}x{} image is too large", width, height)))?;
+    let samples: Vec<usize> = if binary {
+        let data = bytes.get(header.pos + 1..)
+            .ok_or_else(|| invalid_data("netpbm header is not followed by any samples".to_string()))?;
+        let sample_bytes = if max_value > u8::MAX as usize {2} else {1};
+        if data.len() < num_samples * sample_bytes {
+            return Err(invalid_data(format!("{}x{} image needs {} bytes of samples, found {}", width, height, num_samples * sample_bytes, data.len())));
+        }
+        data.chunks(sample_bytes).take(num_samples)
+            .map(|b| if sample_bytes == 2 {(b[0] as usize) << 8 | b[1] as usize} else {b[0] as usize})
+            .collect()
+    } else {
+        (0..num_samples).map(|_| header.number()).collect::<io::Result<Vec<usize>>>()?
+    };
+    if let Some(sample) = samples.iter().find(|s| **s > max_value) {
+        return Err(invalid_data(format!("sample {} exceeds maximum value {}", sample, max_value)));
+    }
+    let pixels: Vec<u8> = samples.iter().map(|s| (s * u8::MAX as usize / max_value) as u8).collect();
+
+    Ok(if channels == 1 {
+        Image::from_pixels(width, height, pixels)
+    } else {
+        match mode {
+            ColorMode::Grayscale => Image::from_pixels(width, height, pixels.chunks(3)
+                .map(|rgb| luminance(rgb[0], rgb[1], rgb[2]))
+                .collect()),
+            ColorMode::Channels => Image::from_pixels(width, 3 * height, (0..3)
+                .flat_map(|c| pixels.iter().skip(c).step_by(3).copied())
+                .collect()).with_planes(3)
+        }
+    })
+}
+
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl <'a> HeaderReader<'a> {
+    fn token(&mut self) -> io::Result<String> {
+        loop {
+            while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if self.pos < self.bytes.len() && self.bytes[self.pos] == b'#' {
+                while self.pos < self.bytes.len() && self.bytes[self.pos] != b'\n' {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        let start = self.pos;
+        while self.pos < self.bytes.len() && !self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            Err(invalid_data("unexpected end of netpbm data".to_string()))
+        } else {
+            Ok(String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned())
+        }
+    }
+
+    fn number(&mut self) -> io::Result<usize> {
+        let token = self.token()?;
+        token.parse().map_err(|_| invalid_data(format!("expected a number, found \"{}\"", token)))
+    }
+}
+
+pub fn write_pgm(file_name: &str, img: &Image) -> io::Result<()> {
+    let mut file = fs::File::create(file_name)?;
+    write!(file, "P5\n{} {}\n255\n", img.width(), img.height())?;
+    let pixels: Vec<u8> = img.x_y_iter().map(|(x, y)| img.get(x, y)).collect();
+    file.write_all(&pixels)
+}
+
+/// Loads every netpbm file in the class subdirectories of `dir`. Labels are assigned in
+/// sorted order of `class_names`, which should list every class of every split so that
+/// training and testing labels agree. All of the images must be the same size.
+pub fn load_labeled_folders(dir: &str, class_names: &[String], mode: ColorMode) -> io::Result<Vec<(u8,Image)>> {
+    let mut result = Vec::new();
+    for (class, file_name) in image_files(dir)? {
+        let label = class_names.iter().position(|name| *name == class).ok_or_else(||
+            invalid_data(format!("{}: class \"{}\" is not one of {:?}", dir, class, class_names)))?;
+        let img = read_pnm(&file_name, mode)?;
+        if let Some((_, first)) = result.first() {
+            if !same_shape(&img, first) {
+                return Err(invalid_data(format!("{}: {}x{} image differs in size from the {}x{} images before it",
+                                                file_name, img.width(), img.height(), first.width(), first.height())));
+            }
+        }
+        result.push((label as u8, img));
+    }
+    Ok(result)
+}
+
+/// The class and path of each image file in the class subdirectories of `dir`, in order
+/// of class and then file name.
+pub fn image_files(dir: &str) -> io::Result<Vec<(String,String)>> {
+    let mut result = Vec::new();
+    for class in sorted_entries(dir, true)? {
+        let class_dir = Path::new(dir).join(&class);
+        for file in sorted_entries(&class_dir.to_string_lossy(), false)? {
+            let is_image = Path::new(&file).extension()
+                .is_some_and(|ext| EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)));
+            if is_image {
+                result.push((class.clone(), class_dir.join(&file).to_string_lossy().into_owned()));
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn same_shape(img: &Image, other: &Image) -> bool {
+    img.same_size(other) && img.planes() == other.planes()
+}
+
+/// The sorted union of the class subdirectory names in each of `dirs`.
+pub fn class_names(dirs: &[String]) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for dir in dirs {
+        for name in sorted_entries(dir, true)? {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    if names.len() > u8::MAX as usize + 1 {
+        return Err(invalid_data(format!("{} classes exceed the {} labels available", names.len(), u8::MAX as usize + 1)));
+    }
+    Ok(names)
+}
+
+/// Writes each image to `dir/<class>/<index>.pgm`, where the class is its label name if
+/// `class_names` is given, and its label number otherwise.
+pub fn export_labeled_folders(dir: &str, images: &[(u8,Image)], class_names: Option<&[String]>) -> io::Result<()> {
+    for (i, (label, img)) in images.iter().enumerate() {
+        let class = class_names.map_or_else(|| label.to_string(), |names| names[*label as usize].clone());
+        let class_dir = Path::new(dir).join(class);
+        fs::create_dir_all(&class_dir)?;
+        write_pgm(&class_dir.join(format!("{}.pgm", i)).to_string_lossy(), img)?;
+    }
+    Ok(())
+}
+
+fn sorted_entries(dir: &str, directories: bool) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() == directories {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ascii = parse_pnm(b"P2\n# a comment\n3 2\n15\n0 1 2\n3 4 15\n", ColorMode::Grayscale).unwrap();
+        assert_eq!(Image::from_pixels(3, 2, vec![0, 17, 34, 51, 68, 255]), ascii);
+
+        let binary = parse_pnm(b"P5 2 2 255\n\x01\x02\x03\x04", ColorMode::Grayscale).unwrap();
+        assert_eq!(Image::from_pixels(2, 2, vec![1, 2, 3, 4]), binary);
+
+        let color = b"P6\n2 1\n255\n\x0a\x14\x1e\xff\x00\x00";
+        assert_eq!(Image::from_pixels(2, 1, vec![18, luminance(255, 0, 0)]), parse_pnm(color, ColorMode::Grayscale).unwrap());
+        assert_eq!(Image::from_pixels(2, 3, vec![10, 255, 20, 0, 30, 0]).with_planes(3), parse_pnm(color, ColorMode::Channels).unwrap());
+
+        assert!(parse_pnm(b"P4 1 1\n\x00", ColorMode::Grayscale).is_err());
+        assert!(parse_pnm(b"P5 2 2 255\n\x01\x02", ColorMode::Grayscale).is_err());
+        assert!(parse_pnm(b"P2 1 1 10 11", ColorMode::Grayscale).is_err());
+        assert!(parse_pnm(b"P5 1 1 255", ColorMode::Grayscale).is_err());
+        assert!(parse_pnm(b"P5 4294967296 4294967296 255\n\x00", ColorMode::Grayscale).is_err());
+    }
+
+    #[test]
+    fn test_folders() -> io::Result<()> {
+        let dir = "test_netpbm_folders";
+        let images = vec![(0, Image::from_pixels(2, 1, vec![1, 2])), (1, Image::from_pixels(2, 1, vec![3, 4])),
+                          (1, Image::from_pixels(2, 1, vec![5, 6]))];
+        let names = vec!["cat".to_string(), "dog".to_string()];
+        export_labeled_folders(dir, &images, Some(&names))?;
+        assert_eq!(names, class_names(&[dir.to_string()])?);
+        let loaded = load_labeled_folders(dir, &names, ColorMode::Grayscale)?;
+        let files = image_files(dir)?;
+        export_labeled_folders(dir, &[(1, Image::from_pixels(1, 2, vec![7, 8]))], Some(&names))?;
+        let mixed = load_labeled_folders(dir, &names, ColorMode::Grayscale);
+        fs::remove_dir_all(dir)?;
+        assert_eq!(images, loaded);
+        assert_eq!(vec!["cat", "dog", "dog"], files.iter().map(|(class, _)| class.as_str()).collect::<Vec<_>>());
+        assert!(mixed.is_err());
+        Ok(())
+    }
+}
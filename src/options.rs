@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::io;
+use std::str::FromStr;
+
+/// A command-line flag, or an option followed by a value when `value` names that value.
+pub struct OptionSpec {
+    pub name: &'static str,
+    pub value: Option<&'static str>,
+    pub help: String,
+}
+
+impl OptionSpec {
+    pub fn flag(name: &'static str, help: String) -> OptionSpec {
+        OptionSpec {name, value: None, help}
+    }
+
+    pub fn valued(name: &'static str, value: &'static str, help: String) -> OptionSpec {
+        OptionSpec {name, value: Some(value), help}
+    }
+}
+
+/// A group of options in the help message. `notes` are printed before the options.
+pub struct Section {
+    pub title: &'static str,
+    pub notes: Vec<String>,
+    pub options: Vec<OptionSpec>,
+}
+
+pub fn usage(program: &str, sections: &[Section]) -> String {
+    let mut result = format!("Usage: {} [options]:\n", program);
+    for (i, section) in sections.iter().enumerate() {
+        if i > 0 {
+            result.push_str(&format!("\n{}:\n", section.title));
+        }
+        section.notes.iter().for_each(|note| result.push_str(&format!("{}\n", note)));
+        for option in section.options.iter() {
+            match option.value {
+                Some(value) => result.push_str(&format!("\t{} {}: {}\n", option.name, value, option.help)),
+                None => result.push_str(&format!("\t{}: {}\n", option.name, option.help))
+            }
+        }
+    }
+    result
+}
+
+#[derive(Clone)]
+pub struct Args {
+    flags: HashSet<String>,
+    values: HashMap<String,String>,
+}
+
+impl Args {
+    /// Rejects arguments that are not defined in `sections`, options missing their value,
+    /// and options given more than once.
+    pub fn parse(args: &[String], sections: &[Section]) -> io::Result<Args> {
+        let specs: HashMap<&str,&OptionSpec> = sections.iter()
+            .flat_map(|section| section.options.iter())
+            .map(|spec| (spec.name, spec))
+            .collect();
+        let mut result = Args {flags: HashSet::new(), values: HashMap::new()};
+        let mut remaining = args.iter();
+        while let Some(arg) = remaining.next() {
+            let spec = specs.get(arg.as_str())
+                .ok_or_else(|| invalid_input(format!("unknown argument \"{}\"; run with \"help\" to list the options", arg)))?;
+            if spec.value.is_some() {
+                let value = remaining.next().ok_or_else(|| invalid_input(format!("{} needs a value", arg)))?;
+                if result.values.insert(arg.clone(), value.clone()).is_some() {
+                    return Err(invalid_input(format!("{} is given more than once", arg)));
+                }
+            } else {
+                result.flags.insert(arg.clone());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Adds the flags of `overrides`, whose option values replace any given here.
+    pub fn overridden_by(mut self, overrides: &Args) -> Args {
+        self.flags.extend(overrides.flags.iter().cloned());
+        self.values.extend(overrides.values.iter().map(|(option, value)| (option.clone(), value.clone())));
+        self
+    }
+
+    pub fn contains(&self, flag: &str) -> bool {
+        self.flags.contains(flag)
+    }
+
+    pub fn value(&self, option: &str) -> Option<String> {
+        self.values.get(option).cloned()
+    }
+
+    pub fn parse_value<T: FromStr>(&self, option: &str) -> io::Result<Option<T>> {
+        match self.values.get(option) {
+            None => Ok(None),
+            Some(value) => value.parse().map(Some).map_err(|_| invalid_input(format!("invalid value \"{}\" for {}", value, option)))
+        }
+    }
+
+    pub fn parse_at_least<T: FromStr + PartialOrd + Display>(&self, option: &str, min: T) -> io::Result<Option<T>> {
+        match self.parse_value(option)? {
+            Some(value) if value < min => Err(invalid_input(format!("{} must be at least {}", option, min))),
+            value => Ok(value)
+        }
+    }
+
+    /// A list of numbers and ranges, as accepted by `parse_ranges`.
+    pub fn parse_ranges(&self, option: &str, min: usize) -> io::Result<Option<Vec<usize>>> {
+        match self.values.get(option) {
+            None => Ok(None),
+            Some(list) => parse_ranges(list, min).map(Some).map_err(|e| invalid_input(format!("{}: {}", option, e)))
+        }
+    }
+
+    /// A comma-separated list of values, each at least `min`.
+    pub fn parse_list<T: FromStr + PartialOrd + Display>(&self, option: &str, min: T) -> io::Result<Option<Vec<T>>> {
+        match self.values.get(option) {
+            None => Ok(None),
+            Some(list) => list.split(',')
+                .map(|item| match item.trim().parse() {
+                    Ok(value) if value >= min => Ok(value),
+                    Ok(_) => Err(invalid_input(format!("values of {} must be at least {}", option, min))),
+                    Err(_) => Err(invalid_input(format!("invalid value \"{}\" in {}", item, option)))
+                })
+                .collect::<io::Result<Vec<T>>>()
+                .map(Some)
+        }
+    }
+}
+
+/// A comma-separated list of items, each a number `n`, a range `a-b` or a range with a
+/// step `a-b:step`. Ranges include both ends.
+pub fn parse_ranges(list: &str, min: usize) -> Result<Vec<usize>,String> {
+    let number = |s: &str| -> Result<usize,String> {
+        match s.trim().parse() {
+            Ok(n) if n >= min => Ok(n),
+            Ok(_) => Err(format!("values must be at least {}", min)),
+            Err(_) => Err(format!("invalid value \"{}\"", s.trim()))
+        }
+    };
+    let mut result = Vec::new();
+    for item in list.split(',') {
+        let (range, step) = match item.find(':') {
+            Some(i) => (&item[..i], number(&item[i + 1..]).and_then(|step| if step > 0 {Ok(step)} else {Err("steps must be positive".to_string())})?),
+            None => (item, 1)
+        };
+        match range.find('-') {
+            Some(i) => {
+                let (start, end) = (number(&range[..i])?, number(&range[i + 1..])?);
+                if start > end {
+                    return Err(format!("range \"{}\" is empty", range.trim()));
+                }
+                result.extend((start..=end).step_by(step));
+            }
+            None => result.push(number(range)?)
+        }
+    }
+    Ok(result)
+}
+
+pub fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sections() -> Vec<Section> {
+        vec![Section {title: "General", notes: vec![], options: vec![
+            OptionSpec::flag("permute", "permute pixels".to_string()),
+            OptionSpec::valued("--k", "n", "neighbors".to_string()),
+            OptionSpec::valued("--sequence", "list", "shrink factors".to_string())]}]
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let args = Args::parse(&strings(&["--k", "5", "permute", "--sequence", "4, 2"]), &sections()).unwrap();
+        assert!(args.contains("permute"));
+        assert_eq!(Some(5), args.parse_at_least("--k", 1).unwrap());
+        assert_eq!(Some(vec![4, 2]), args.parse_list("--sequence", 1).unwrap());
+        assert_eq!(None, args.value("--missing"));
+        assert!(args.parse_at_least("--k", 6).is_err());
+        assert!(args.parse_list("--sequence", 3).is_err());
+
+        assert!(Args::parse(&strings(&["permutee"]), &sections()).is_err());
+        assert!(Args::parse(&strings(&["--k"]), &sections()).is_err());
+        assert!(Args::parse(&strings(&["--k", "1", "--k", "2"]), &sections()).is_err());
+        assert!(Args::parse(&strings(&["--k", "x"]), &sections()).unwrap().parse_value::<usize>("--k").is_err());
+    }
+
+    #[test]
+    fn test_override() {
+        let file = Args::parse(&strings(&["--k", "5", "--sequence", "4"]), &sections()).unwrap();
+        let args = file.overridden_by(&Args::parse(&strings(&["--k", "3", "permute"]), &sections()).unwrap());
+        assert_eq!(Some(3), args.parse_value("--k").unwrap());
+        assert_eq!(Some("4".to_string()), args.value("--sequence"));
+        assert!(args.contains("permute"));
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(Ok(vec![1, 3, 5, 7, 8, 9, 20]), parse_ranges("1-7:2, 8-9,20", 1));
+        assert_eq!(Ok(vec![4]), parse_ranges("4-4", 1));
+        assert!(parse_ranges("5-3", 1).is_err());
+        assert!(parse_ranges("0-3", 1).is_err());
+        assert!(parse_ranges("1-3:0", 1).is_err());
+        assert!(parse_ranges("1,,2", 1).is_err());
+    }
+
+    #[test]
+    fn test_usage() {
+        assert_eq!("Usage: test [options]:\n\tpermute: permute pixels\n\t--k n: neighbors\n\t--sequence list: shrink factors\n", usage("test", &sections()));
+    }
+}
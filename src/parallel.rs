@@ -0,0 +1,67 @@
+use std::io;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// One thread for each available CPU core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Applies `f` to each item on up to `threads` threads. Each thread takes a contiguous
+/// share of `items`, so the results are in the order of `items` however many threads run.
+pub fn map<T: Sync, R: Send, F: Fn(&T) -> R + Sync>(items: &[T], threads: usize, f: F) -> Vec<R> {
+    map_reporting(items, threads, f, || {})
+}
+
+/// `map`, printing the percentage of items done every 5%.
+pub fn map_with_progress<T: Sync, R: Send, F: Fn(&T) -> R + Sync>(items: &[T], threads: usize, f: F) -> Vec<R> {
+    let done = AtomicUsize::new(0);
+    let result = map_reporting(items, threads, f, || {
+        let count = done.fetch_add(1, Ordering::SeqCst) + 1;
+        let twentieths = count * 20 / items.len();
+        if twentieths > (count - 1) * 20 / items.len() {
+            print!("{}%; ", twentieths * 5);
+            io::stdout().flush().expect("Could not flush stdout");
+        }
+    });
+    println!();
+    result
+}
+
+/// Calls `report` after each item is done.
+fn map_reporting<T: Sync, R: Send, F: Fn(&T) -> R + Sync, P: Fn() + Sync>(items: &[T], threads: usize, f: F, report: P) -> Vec<R> {
+    let apply = |item: &T| {
+        let result = f(item);
+        report();
+        result
+    };
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(apply).collect();
+    }
+    let chunk_size = items.len().div_ceil(threads);
+    let apply = &apply;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(apply).collect::<Vec<R>>()))
+            .collect();
+        handles.into_iter()
+            .flat_map(|handle| handle.join().expect("worker thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order() {
+        let items: Vec<usize> = (0..103).collect();
+        let expected: Vec<usize> = items.iter().map(|i| i * i).collect();
+        for threads in 1..=8 {
+            assert_eq!(expected, map(&items, threads, |i| i * i));
+        }
+        assert_eq!(Vec::<usize>::new(), map(&[], 4, |i: &usize| *i));
+    }
+}
@@ -1,5 +1,5 @@
 #[cfg(test)]
-use rand::thread_rng;
+use rand::Rng;
 #[cfg(test)]
 use rand::seq::SliceRandom;
 use std::io;
@@ -7,12 +7,12 @@ use std::fs::File;
 #[cfg(test)]
 use std::io::Write;
 use std::io::Read;
+use crate::idx::invalid_data;
 
 #[cfg(test)]
-pub fn make_permutation(n: usize) -> Vec<usize> {
-    let mut rng = thread_rng();
+pub fn make_permutation<R: Rng>(n: usize, rng: &mut R) -> Vec<usize> {
     let mut indices: Vec<usize> = (0..n).collect();
-    indices.shuffle(&mut rng);
+    indices.shuffle(rng);
     indices
 }
 
@@ -26,37 +26,68 @@ pub fn write_permutation(filename: &str, nums: &Vec<usize>) -> io::Result<()> {
     Ok(())
 }
 
-pub fn read_permutation(filename: &str) -> io::Result<Vec<usize>> {
+/// Reads a permutation of `0..size` written as comma-separated indices.
+pub fn read_permutation(filename: &str, size: usize) -> io::Result<Vec<usize>> {
     let mut file = File::open(filename)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
-    Ok(contents.split(',')
-        .map(|n| n.parse())
-        .filter_map(Result::ok)
-        .collect())
+    parse_permutation(&contents, size).map_err(|e| invalid_data(format!("{}: {}", filename, e)))
+}
+
+/// Empty entries, such as after the final comma, are skipped. Every index in `0..size`
+/// must appear exactly once.
+fn parse_permutation(contents: &str, size: usize) -> io::Result<Vec<usize>> {
+    let permutation = contents.split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| entry.parse().map_err(|_| invalid_data(format!("invalid entry \"{}\"", entry))))
+        .collect::<io::Result<Vec<usize>>>()?;
+    if permutation.len() != size {
+        return Err(invalid_data(format!("the permutation has {} entries, but the images have {} pixels", permutation.len(), size)));
+    }
+    let mut seen = vec![false; size];
+    for index in permutation.iter() {
+        match seen.get_mut(*index) {
+            None => return Err(invalid_data(format!("entry {} is out of range for {} pixels", index, size))),
+            Some(true) => return Err(invalid_data(format!("entry {} appears more than once", index))),
+            Some(seen) => *seen = true
+        }
+    }
+    Ok(permutation)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::permutation::{make_permutation, write_permutation, read_permutation};
+    use crate::permutation::{make_permutation, write_permutation, read_permutation, parse_permutation};
     use std::io;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
 
     #[test]
     fn test_size() {
-        let perm = make_permutation(10);
+        let perm = make_permutation(10, &mut StdRng::seed_from_u64(1));
         assert_eq!(10, perm.len());
     }
 
     #[test]
     fn test_file() -> io::Result<()> {
-        let perm = make_permutation(28*28);
+        let perm = make_permutation(28*28, &mut StdRng::seed_from_u64(2));
         assert!(all_values_present(&perm));
         write_permutation("test_file", &perm)?;
-        let read = read_permutation("test_file")?;
+        let read = read_permutation("test_file", 28*28)?;
         assert_eq!(read, perm);
+        assert!(read_permutation("test_file", 32*32).is_err());
         Ok(())
     }
 
+    #[test]
+    fn test_parse() {
+        assert_eq!(vec![2, 0, 1], parse_permutation("2, 0,1,\n", 3).unwrap());
+        for contents in ["2,0,x", "2,0", "2,0,3", "2,0,0"] {
+            assert_eq!(io::ErrorKind::InvalidData, parse_permutation(contents, 3).unwrap_err().kind());
+        }
+    }
+
     fn all_values_present(nums: &Vec<usize>) -> bool {
         for i in 0..nums.len() {
             if !nums.contains(&i) {
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use crate::idx::invalid_data;
+use crate::stats;
+
+/// A row of the table: a variant and its settings, so that the points of a sweep are kept apart.
+pub type Row = (String, String);
+
+/// A column of the table: a shrink factor (`None` for all images) and whether the images
+/// were permuted.
+pub type Column = (Option<usize>, bool);
+
+/// Error rates of each variant under each shrink factor and condition, gathered from the
+/// CSV results files of one or more runs.
+pub struct ErrorTable {
+    rows: Vec<Row>,
+    columns: Vec<Column>,
+    cells: BTreeMap<(Row,Column),Vec<f64>>,
+}
+
+/// Returns `(row, column, error_rate)` for each record of a results file written by
+/// `--output` in CSV format. Files without a `settings` column give empty settings.
+pub fn read_csv_results(file_name: &str) -> io::Result<Vec<(Row,Column,f64)>> {
+    let text = fs::read_to_string(file_name)?;
+    parse_csv_results(&text).map_err(|e| invalid_data(format!("{}: {}", file_name, e)))
+}
+
+pub fn parse_csv_results(text: &str) -> io::Result<Vec<(Row,Column,f64)>> {
+    let mut lines = text.lines().filter(|line| !line.starts_with('#') && !line.trim().is_empty());
+    let header = split_csv_line(lines.next().ok_or_else(|| invalid_data("no header line".to_string()))?);
+    let column_of = |name: &str| header.iter().position(|h| h == name)
+        .ok_or_else(|| invalid_data(format!("no \"{}\" column", name)));
+    let (shrink, condition, variant, error_rate) = (column_of("shrink")?, column_of("condition")?, column_of("variant")?, column_of("error_rate")?);
+    let settings = column_of("settings").ok();
+
+    let mut result = Vec::new();
+    for line in lines {
+        let fields = split_csv_line(line);
+        if fields.len() != header.len() {
+            return Err(invalid_data(format!("expected {} fields, found {}: {}", header.len(), fields.len(), line)));
+        }
+        let shrink = if fields[shrink].is_empty() {
+            None
+        } else {
+            Some(fields[shrink].parse().map_err(|_| invalid_data(format!("invalid shrink \"{}\"", fields[shrink])))?)
+        };
+        let permuted = match fields[condition].as_str() {
+            "original" => false,
+            "permuted" => true,
+            other => return Err(invalid_data(format!("unknown condition \"{}\"", other)))
+        };
+        let error = fields[error_rate].parse().map_err(|_| invalid_data(format!("invalid error rate \"{}\"", fields[error_rate])))?;
+        let row = (fields[variant].clone(), settings.map_or(String::new(), |i| fields[i].clone()));
+        result.push((row, (shrink, permuted), error));
+    }
+    Ok(result)
+}
+
+/// Splits on commas outside of double quotes; `""` within quotes is a literal quote.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c)
+        }
+    }
+    fields
+}
+
+impl ErrorTable {
+    /// Variants appear in the order of `variant_order`, followed by any others in sorted
+    /// order, with the settings of each in the order they were first run. Columns go from
+    /// the largest shrink factor to all images, original before permuted.
+    pub fn new(results: &[(Row,Column,f64)], variant_order: &[&str]) -> ErrorTable {
+        let mut cells: BTreeMap<(Row,Column),Vec<f64>> = BTreeMap::new();
+        for (row, column, error) in results.iter() {
+            cells.entry((row.clone(), *column)).or_default().push(*error);
+        }
+        let mut variants: Vec<String> = variant_order.iter()
+            .filter(|v| results.iter().any(|((variant, _), _, _)| variant == *v))
+            .map(|v| v.to_string())
+            .collect();
+        let mut others: Vec<String> = results.iter()
+            .map(|((variant, _), _, _)| variant.clone())
+            .filter(|v| !variant_order.contains(&v.as_str()))
+            .collect();
+        others.sort();
+        others.dedup();
+        variants.append(&mut others);
+        let mut rows: Vec<Row> = Vec::new();
+        for variant in variants.iter() {
+            for (row, _, _) in results.iter() {
+                if row.0 == *variant && !rows.contains(row) {
+                    rows.push(row.clone());
+                }
+            }
+        }
+
+        let mut columns: Vec<Column> = results.iter().map(|(_, column, _)| *column).collect();
+        columns.sort_by_key(|(shrink, permuted)| (shrink.is_none(), std::cmp::Reverse(*shrink), *permuted));
+        columns.dedup();
+        ErrorTable {rows, columns, cells}
+    }
+
+    /// The variant, followed by its settings if it was run with several.
+    fn row_name(&self, row: &Row) -> String {
+        if self.rows.iter().filter(|(variant, _)| *variant == row.0).count() > 1 {
+            format!("{} ({})", row.0, row.1)
+        } else {
+            row.0.clone()
+        }
+    }
+
+    /// The mean error rate, followed by its standard deviation when several runs contributed.
+    fn cell(&self, row: &Row, column: Column, plus_minus: &str) -> Option<String> {
+        self.cells.get(&(row.clone(), column)).map(|errors|
+            if errors.len() == 1 {
+                format!("{:.2}", errors[0])
+            } else {
+                format!("{:.2} {} {:.2}", stats::mean(errors), plus_minus, stats::stdev(errors))
+            })
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut result = String::from("| Variant |");
+        self.columns.iter().for_each(|column| result.push_str(&format!(" {} {} |", shrink_name(column.0), condition_name(column.1))));
+        result.push_str("\n|---|");
+        self.columns.iter().for_each(|_| result.push_str("---:|"));
+        result.push('\n');
+        for row in self.rows.iter() {
+            result.push_str(&format!("| {} |", self.row_name(row)));
+            for column in self.columns.iter() {
+                result.push_str(&format!(" {} |", self.cell(row, *column, "±").unwrap_or_else(|| "–".to_string())));
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    pub fn to_latex(&self) -> String {
+        let shrinks = self.shrink_groups();
+        let mut result = format!("\\begin{{tabular}}{{l{}}}\n\\hline\n", shrinks.iter().map(|(_, n)| format!("|{}", "r".repeat(*n))).collect::<String>());
+        for (shrink, n) in shrinks.iter() {
+            result.push_str(&format!(" & \\multicolumn{{{}}}{{c}}{{{}}}", n, latex_escape(&shrink_name(*shrink))));
+        }
+        result.push_str(" \\\\\nVariant");
+        self.columns.iter().for_each(|column| result.push_str(&format!(" & {}", capitalized(condition_name(column.1)))));
+        result.push_str(" \\\\\n\\hline\n");
+        for row in self.rows.iter() {
+            result.push_str(&latex_escape(&self.row_name(row)));
+            for column in self.columns.iter() {
+                result.push_str(&format!(" & {}", self.cell(row, *column, "$\\pm$").unwrap_or_else(|| "--".to_string())));
+            }
+            result.push_str(" \\\\\n");
+        }
+        result.push_str("\\hline\n\\end{tabular}\n");
+        result
+    }
+
+    /// Each shrink factor with the number of adjacent columns it spans.
+    fn shrink_groups(&self) -> Vec<(Option<usize>,usize)> {
+        let mut groups: Vec<(Option<usize>,usize)> = Vec::new();
+        for (shrink, _) in self.columns.iter() {
+            match groups.last_mut() {
+                Some((last, n)) if last == shrink => *n += 1,
+                _ => groups.push((*shrink, 1))
+            }
+        }
+        groups
+    }
+}
+
+fn shrink_name(shrink: Option<usize>) -> String {
+    shrink.map_or("all".to_string(), |s| format!("1/{}", s))
+}
+
+fn condition_name(permuted: bool) -> &'static str {
+    if permuted {"permuted"} else {"original"}
+}
+
+fn capitalized(s: &str) -> String {
+    s[..1].to_uppercase() + &s[1..]
+}
+
+fn latex_escape(s: &str) -> String {
+    s.replace('\\', "\\textbackslash{}").replace('_', "\\_").replace('%', "\\%").replace('&', "\\&").replace('#', "\\#")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "# dataset: mnist\n\
+        seed,shrink,trial,trial_seed,condition,variant,error_rate\n\
+        1,50,1,1,original,brief,10\n\
+        1,50,1,1,permuted,brief,20\n\
+        1,50,1,1,original,baseline,5\n\
+        1,50,2,9,original,brief,12\n\
+        1,,1,1,original,gaussian_7,3.5\n";
+
+    #[test]
+    fn test_parse() {
+        let results = parse_csv_results(CSV).unwrap();
+        assert_eq!(5, results.len());
+        assert_eq!((("brief".to_string(), String::new()), (Some(50), true), 20.0), results[1]);
+        assert_eq!((("gaussian_7".to_string(), String::new()), (None, false), 3.5), results[4]);
+        assert_eq!(vec!["a", "b,\"c\"", ""], split_csv_line("a,\"b,\"\"c\"\"\","));
+        assert!(parse_csv_results("shrink,condition,variant,error_rate\n,unknown,brief,1\n").is_err());
+    }
+
+    #[test]
+    fn test_tables() {
+        let table = ErrorTable::new(&parse_csv_results(CSV).unwrap(), &["baseline", "brief"]);
+        assert_eq!("| Variant | 1/50 original | 1/50 permuted | all original |\n\
+                    |---|---:|---:|---:|\n\
+                    | baseline | 5.00 | – | – |\n\
+                    | brief | 11.00 ± 1.41 | 20.00 | – |\n\
+                    | gaussian_7 | – | – | 3.50 |\n", table.to_markdown());
+        assert_eq!("\\begin{tabular}{l|rr|r}\n\\hline\n\
+                    \x20& \\multicolumn{2}{c}{1/50} & \\multicolumn{1}{c}{all} \\\\\n\
+                    Variant & Original & Permuted & Original \\\\\n\\hline\n\
+                    baseline & 5.00 & -- & -- \\\\\n\
+                    brief & 11.00 $\\pm$ 1.41 & 20.00 & -- \\\\\n\
+                    gaussian\\_7 & -- & -- & 3.50 \\\\\n\
+                    \\hline\n\\end{tabular}\n", table.to_latex());
+    }
+
+    #[test]
+    fn test_sweep() {
+        let csv = "shrink,condition,variant,settings,error_rate\n\
+                   ,original,baseline,raw distance=euclidean k=3,4\n\
+                   ,original,baseline,raw distance=euclidean k=1,2\n\
+                   ,original,baseline,raw distance=euclidean k=3,6\n\
+                   ,original,brief,\"gaussian_brief pairs=10, distance=hamming\",8\n";
+        let table = ErrorTable::new(&parse_csv_results(csv).unwrap(), &["baseline", "brief"]);
+        assert_eq!("| Variant | all original |\n\
+                    |---|---:|\n\
+                    | baseline (raw distance=euclidean k=3) | 5.00 ± 1.41 |\n\
+                    | baseline (raw distance=euclidean k=1) | 2.00 |\n\
+                    | brief | 8.00 |\n", table.to_markdown());
+    }
+}
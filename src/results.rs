@@ -0,0 +1,220 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::training_harness::ConfusionMatrix;
+
+/// The outcome of testing one variant on one set of images.
+#[derive(Clone)]
+pub struct VariantResult {
+    pub variant: String,
+    /// The variant's features, distance and k, as written by `Variant`'s `Display`.
+    pub settings: String,
+    pub training_images: usize,
+    pub testing_images: usize,
+    /// Percentage; with cross-validation, the mean over the folds. With `--reject`, of the
+    /// testing images that were not rejected, as is `confusion`.
+    pub error_rate: f64,
+    pub fold_error_rates: Vec<f64>,
+    /// With `--k-max`, the error rate of each k from 1 up, from the same neighbor search;
+    /// with cross-validation, pooled over the folds.
+    pub error_rates_by_k: Vec<f64>,
+    /// With `--reject`, the minimum margins with the coverage and accuracy of rejecting
+    /// the classifications below each.
+    pub coverage_curve: Vec<(f64,f64,f64)>,
+    /// With cross-validation, the sum of the folds' matrices.
+    pub confusion: ConfusionMatrix,
+    pub conversion_ms: u128,
+    pub training_ms: u128,
+    pub testing_ms: u128,
+}
+
+/// A `VariantResult` together with the conditions of the experiment that produced it.
+pub struct ResultRecord {
+    pub shrink: Option<usize>,
+    pub trial: usize,
+    pub seed: u64,
+    pub permuted: bool,
+    pub result: VariantResult,
+}
+
+pub struct RunResults {
+    /// Option names and values describing how the run was configured.
+    pub configuration: Vec<(String,String)>,
+    pub seed: u64,
+    pub records: Vec<ResultRecord>,
+}
+
+const CSV_HEADER: &str = "seed,shrink,trial,trial_seed,condition,variant,settings,training_images,testing_images,rejected,error_rate,fold_error_rates,error_rates_by_k,coverage_curve,conversion_ms,training_ms,testing_ms,confusion_matrix";
+
+impl RunResults {
+    pub fn new(configuration: Vec<(String,String)>, seed: u64) -> RunResults {
+        RunResults {configuration, seed, records: Vec::new()}
+    }
+
+    /// Writes CSV if `file_name` ends in `.csv` and JSON if it ends in `.json`.
+    pub fn write(&self, file_name: &str) -> io::Result<()> {
+        let contents = match Path::new(file_name).extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => self.to_csv(),
+            Some("json") => self.to_json(),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}: results file must end in .csv or .json", file_name)))
+        };
+        fs::write(file_name, contents)
+    }
+
+    /// One row per record. The configuration is written as leading `#` comment lines, each
+    /// coverage curve as space-separated `margin=coverage/accuracy` entries, and each
+    /// confusion matrix as space-separated `true>classified=count` entries.
+    pub fn to_csv(&self) -> String {
+        let mut result = String::new();
+        for (name, value) in self.configuration.iter() {
+            result.push_str(&format!("# {}: {}\n", name, value));
+        }
+        result.push_str(CSV_HEADER);
+        result.push('\n');
+        for record in self.records.iter() {
+            let r = &record.result;
+            let fields = vec![
+                self.seed.to_string(),
+                record.shrink.map_or(String::new(), |s| s.to_string()),
+                (record.trial + 1).to_string(),
+                record.seed.to_string(),
+                condition(record).to_string(),
+                csv_field(&r.variant),
+                csv_field(&r.settings),
+                r.training_images.to_string(),
+                r.testing_images.to_string(),
+                r.confusion.rejected().to_string(),
+                r.error_rate.to_string(),
+                r.fold_error_rates.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" "),
+                r.error_rates_by_k.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" "),
+                r.coverage_curve.iter()
+                    .map(|(min_margin, coverage, accuracy)| format!("{}={}/{}", min_margin, coverage, accuracy))
+                    .collect::<Vec<_>>().join(" "),
+                r.conversion_ms.to_string(),
+                r.training_ms.to_string(),
+                r.testing_ms.to_string(),
+                matrix_entries(&r.confusion).iter()
+                    .map(|(img_label, classification, count)| format!("{}>{}={}", img_label, classification, count))
+                    .collect::<Vec<_>>().join(" ")
+            ];
+            result.push_str(&fields.join(","));
+            result.push('\n');
+        }
+        result
+    }
+
+    pub fn to_json(&self) -> String {
+        let configuration: Vec<String> = self.configuration.iter()
+            .map(|(name, value)| format!("{}: {}", json_string(name), json_string(value)))
+            .collect();
+        let records: Vec<String> = self.records.iter().map(record_json).collect();
+        format!("{{\n  \"configuration\": {{{}}},\n  \"seed\": {},\n  \"results\": [\n    {}\n  ]\n}}\n",
+                configuration.join(", "), self.seed, records.join(",\n    "))
+    }
+}
+
+fn condition(record: &ResultRecord) -> &'static str {
+    if record.permuted {"permuted"} else {"original"}
+}
+
+fn record_json(record: &ResultRecord) -> String {
+    let r = &record.result;
+    let labels = r.confusion.labels();
+    let rows: Vec<String> = labels.iter()
+        .map(|img_label| json_list(labels.iter().map(|classification| r.confusion.count(*img_label, *classification))))
+        .collect();
+    let coverage_curve = r.coverage_curve.iter()
+        .map(|(min_margin, coverage, accuracy)| format!("{{\"min_margin\": {}, \"coverage\": {}, \"accuracy\": {}}}",
+                                                        json_number(*min_margin), json_number(*coverage), json_number(*accuracy)));
+    format!("{{\"shrink\": {}, \"trial\": {}, \"seed\": {}, \"condition\": \"{}\", \"variant\": {}, \"settings\": {}, \"training_images\": {}, \"testing_images\": {}, \"rejected\": {}, \
+             \"error_rate\": {}, \"fold_error_rates\": {}, \"error_rates_by_k\": {}, \"coverage_curve\": {}, \"times_ms\": {{\"conversion\": {}, \"training\": {}, \"testing\": {}}}, \
+             \"confusion_matrix\": {{\"labels\": {}, \"counts\": [{}]}}}}",
+            record.shrink.map_or("null".to_string(), |s| s.to_string()), record.trial + 1, record.seed, condition(record),
+            json_string(&r.variant), json_string(&r.settings), r.training_images, r.testing_images, r.confusion.rejected(), json_number(r.error_rate),
+            json_list(r.fold_error_rates.iter().map(|e| json_number(*e))), json_list(r.error_rates_by_k.iter().map(|e| json_number(*e))), json_list(coverage_curve),
+            r.conversion_ms, r.training_ms, r.testing_ms, json_list(labels.iter()), rows.join(", "))
+}
+
+fn matrix_entries(matrix: &ConfusionMatrix) -> Vec<(u8,u8,usize)> {
+    let labels = matrix.labels();
+    labels.iter()
+        .flat_map(|img_label| labels.iter().map(move |classification| (*img_label, *classification)))
+        .map(|(img_label, classification)| (img_label, classification, matrix.count(img_label, classification)))
+        .filter(|(_, _, count)| *count > 0)
+        .collect()
+}
+
+fn json_list<T: ToString, I: Iterator<Item=T>>(items: I) -> String {
+    format!("[{}]", items.map(|item| item.to_string()).collect::<Vec<_>>().join(", "))
+}
+
+/// JSON has no NaN or infinity, so they are written as `null`.
+fn json_number(x: f64) -> String {
+    if x.is_finite() {x.to_string()} else {"null".to_string()}
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> RunResults {
+        let mut confusion = ConfusionMatrix::new();
+        confusion.record(0, 0);
+        confusion.record(0, 0);
+        confusion.record(1, 0);
+        confusion.record_or_reject(1, 0, 0.25, 0.5);
+        let result = VariantResult {variant: "brief".to_string(), settings: "gaussian_brief pairs=10 distance=hamming k=7".to_string(), training_images: 10, testing_images: 4, error_rate: 100.0 / 3.0,
+            fold_error_rates: Vec::new(), error_rates_by_k: vec![50.0, 100.0 / 3.0], coverage_curve: vec![(0.0, 1.0, 2.0 / 3.0), (0.5, 0.25, 1.0)], confusion, conversion_ms: 5, training_ms: 1, testing_ms: 7};
+        let mut results = RunResults::new(vec![("dataset".to_string(), "mnist".to_string())], 42);
+        results.records.push(ResultRecord {shrink: Some(50), trial: 0, seed: 42, permuted: true, result});
+        results
+    }
+
+    #[test]
+    fn test_csv() {
+        let csv = example().to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(vec!["# dataset: mnist", CSV_HEADER], lines[..2].to_vec());
+        assert_eq!(format!("42,50,1,42,permuted,brief,gaussian_brief pairs=10 distance=hamming k=7,10,4,1,{0},,50 {0},0=1/{1} 0.5=0.25/1,5,1,7,0>0=2 1>0=1", 100.0 / 3.0, 2.0 / 3.0), lines[2]);
+    }
+
+    #[test]
+    fn test_json() {
+        let json = example().to_json();
+        assert!(json.contains("\"configuration\": {\"dataset\": \"mnist\"}"));
+        assert!(json.contains("\"shrink\": 50, \"trial\": 1, \"seed\": 42, \"condition\": \"permuted\", \"variant\": \"brief\", \"settings\": \"gaussian_brief pairs=10 distance=hamming k=7\""));
+        assert!(json.contains("\"testing_images\": 4, \"rejected\": 1, \"error_rate\""));
+        assert!(json.contains("\"confusion_matrix\": {\"labels\": [0, 1], \"counts\": [[2, 0], [1, 0]]}"));
+        assert!(json.contains("\"coverage_curve\": [{\"min_margin\": 0, \"coverage\": 1, \"accuracy\": 0.6666666666666666}, {\"min_margin\": 0.5, \"coverage\": 0.25, \"accuracy\": 1}]"));
+        assert_eq!("\"a\\\"b\\\\c\\n\"", json_string("a\"b\\c\n"));
+
+        let mut results = example();
+        results.records[0].result.error_rate = f64::NAN;
+        results.records[0].result.fold_error_rates = vec![f64::NAN, 10.0];
+        let json = results.to_json();
+        assert!(json.contains("\"error_rate\": null, \"fold_error_rates\": [null, 10], "));
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use rand::Rng;
+use rand::seq::index;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use crate::mnist_data::{Image, discard};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sampling {
+    /// Keep every n-th example, as in the original experiments.
+    EveryNth,
+    /// Keep the same fraction of each class, chosen at random.
+    Stratified,
+    /// Keep a fraction of all examples, chosen at random regardless of class.
+    Random,
+    /// Keep the given number of examples of each class, chosen at random.
+    PerClass(usize),
+}
+
+impl Sampling {
+    pub fn from_name(name: &str) -> Option<Sampling> {
+        match name {
+            "every" => Some(Sampling::EveryNth),
+            "stratified" => Some(Sampling::Stratified),
+            "random" => Some(Sampling::Random),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sampling::EveryNth => write!(f, "every"),
+            Sampling::Stratified => write!(f, "stratified"),
+            Sampling::Random => write!(f, "random"),
+            Sampling::PerClass(n) => write!(f, "{} per class", n),
+        }
+    }
+}
+
+/// Two sets of labeled examples, such as training and testing images.
+pub type Split<T> = (Vec<(u8,T)>, Vec<(u8,T)>);
+
+pub struct Sampler {
+    method: Sampling,
+    rng: StdRng,
+}
+
+impl Sampler {
+    pub fn new(method: Sampling, seed: u64) -> Sampler {
+        Sampler {method, rng: StdRng::seed_from_u64(seed)}
+    }
+
+    pub fn method(&self) -> Sampling {
+        self.method
+    }
+
+    /// Reduces `items` to roughly one out of every `shrink` examples. `PerClass` sampling
+    /// ignores `shrink`.
+    pub fn shrink(&mut self, items: &[(u8,Image)], shrink: usize) -> Vec<(u8,Image)> {
+        match self.method {
+            Sampling::EveryNth => discard(items, shrink),
+            Sampling::Stratified => stratified_fraction(items, 1.0 / shrink as f64, &mut self.rng),
+            Sampling::Random => random_fraction(items, 1.0 / shrink as f64, &mut self.rng),
+            Sampling::PerClass(n) => stratified_count(items, n, &mut self.rng),
+        }
+    }
+
+    pub fn split(&mut self, items: &[(u8,Image)], held_out_fraction: f64) -> Split<Image> {
+        stratified_split(items, held_out_fraction, &mut self.rng)
+    }
+
+    pub fn folds(&mut self, items: &[(u8,Image)], num_folds: usize) -> Vec<usize> {
+        stratified_folds(items, num_folds, &mut self.rng)
+    }
+}
+
+pub fn class_indices<T>(items: &[(u8,T)]) -> BTreeMap<u8,Vec<usize>> {
+    let mut result: BTreeMap<u8,Vec<usize>> = BTreeMap::new();
+    for (i, (label, _)) in items.iter().enumerate() {
+        result.entry(*label).or_default().push(i);
+    }
+    result
+}
+
+/// Rounds `count * fraction`, but keeps at least one example of any class that has one.
+fn class_quota(count: usize, fraction: f64) -> usize {
+    if count == 0 {
+        0
+    } else {
+        ((count as f64 * fraction).round() as usize).max(1).min(count)
+    }
+}
+
+fn choose_from<R: Rng>(indices: &[usize], amount: usize, rng: &mut R) -> Vec<usize> {
+    index::sample(rng, indices.len(), amount.min(indices.len())).into_iter()
+        .map(|i| indices[i])
+        .collect()
+}
+
+/// Selected items keep their original relative order.
+fn select<T: Clone>(items: &[(u8,T)], mut chosen: Vec<usize>) -> Vec<(u8,T)> {
+    chosen.sort_unstable();
+    chosen.iter().map(|i| items[*i].clone()).collect()
+}
+
+pub fn stratified_fraction<T: Clone, R: Rng>(items: &[(u8,T)], fraction: f64, rng: &mut R) -> Vec<(u8,T)> {
+    let chosen = class_indices(items).values()
+        .flat_map(|indices| choose_from(indices, class_quota(indices.len(), fraction), rng))
+        .collect();
+    select(items, chosen)
+}
+
+pub fn stratified_count<T: Clone, R: Rng>(items: &[(u8,T)], per_class: usize, rng: &mut R) -> Vec<(u8,T)> {
+    let chosen = class_indices(items).values()
+        .flat_map(|indices| choose_from(indices, per_class, rng))
+        .collect();
+    select(items, chosen)
+}
+
+pub fn random_fraction<T: Clone, R: Rng>(items: &[(u8,T)], fraction: f64, rng: &mut R) -> Vec<(u8,T)> {
+    let amount = (items.len() as f64 * fraction).round() as usize;
+    select(items, index::sample(rng, items.len(), amount.min(items.len())).into_vec())
+}
+
+/// Returns `(kept, held_out)`, where `held_out` contains `fraction` of each class. At least
+/// one example of each class is kept, so a class with a single example is never held out.
+pub fn stratified_split<T: Clone, R: Rng>(items: &[(u8,T)], fraction: f64, rng: &mut R) -> Split<T> {
+    let mut held_out = vec![false; items.len()];
+    for indices in class_indices(items).values() {
+        let amount = if fraction > 0.0 {class_quota(indices.len(), fraction).min(indices.len() - 1)} else {0};
+        choose_from(indices, amount, rng).iter().for_each(|i| held_out[*i] = true);
+    }
+    let mut kept = Vec::new();
+    let mut out = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        if held_out[i] {out.push(item.clone())} else {kept.push(item.clone())}
+    }
+    (kept, out)
+}
+
+/// Assigns each item to one of `num_folds` folds, so that each class is spread as evenly
+/// as possible across the folds.
+pub fn stratified_folds<T, R: Rng>(items: &[(u8,T)], num_folds: usize, rng: &mut R) -> Vec<usize> {
+    let mut folds = vec![0; items.len()];
+    let mut next_fold = 0;
+    for indices in class_indices(items).values() {
+        for i in choose_from(indices, indices.len(), rng) {
+            folds[i] = next_fold;
+            next_fold = (next_fold + 1) % num_folds;
+        }
+    }
+    folds
+}
+
+/// Returns `(others, members)` of the given fold.
+pub fn split_by_fold<T: Clone>(items: &[(u8,T)], folds: &[usize], fold: usize) -> Split<T> {
+    let mut others = Vec::new();
+    let mut members = Vec::new();
+    for (item, f) in items.iter().zip(folds.iter()) {
+        if *f == fold {members.push(item.clone())} else {others.push(item.clone())}
+    }
+    (others, members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labeled(counts: &[(u8, usize)]) -> Vec<(u8,usize)> {
+        let mut result = Vec::new();
+        for (label, count) in counts {
+            for _ in 0..*count {
+                result.push((*label, result.len()));
+            }
+        }
+        result
+    }
+
+    fn count(items: &[(u8,usize)], label: u8) -> usize {
+        items.iter().filter(|(l, _)| *l == label).count()
+    }
+
+    fn in_order(items: &[(u8,usize)]) -> bool {
+        (1..items.len()).all(|i| items[i - 1].1 < items[i].1)
+    }
+
+    #[test]
+    fn test_stratified() {
+        let items = labeled(&[(0, 100), (1, 50), (2, 3)]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let sample = stratified_fraction(&items, 0.1, &mut rng);
+        assert_eq!(10, count(&sample, 0));
+        assert_eq!(5, count(&sample, 1));
+        assert_eq!(1, count(&sample, 2));
+        assert!(in_order(&sample));
+
+        let sample = stratified_count(&items, 20, &mut rng);
+        assert_eq!((20, 20, 3), (count(&sample, 0), count(&sample, 1), count(&sample, 2)));
+        assert!(in_order(&sample));
+    }
+
+    #[test]
+    fn test_seeded() {
+        let items = labeled(&[(0, 100), (1, 100)]);
+        let sample_1 = random_fraction(&items, 0.25, &mut StdRng::seed_from_u64(7));
+        let sample_2 = random_fraction(&items, 0.25, &mut StdRng::seed_from_u64(7));
+        assert_eq!(50, sample_1.len());
+        assert_eq!(sample_1, sample_2);
+        assert!(in_order(&sample_1));
+    }
+
+    #[test]
+    fn test_folds() {
+        let items = labeled(&[(0, 30), (1, 12), (2, 2)]);
+        let folds = stratified_folds(&items, 3, &mut StdRng::seed_from_u64(5));
+        for fold in 0..3 {
+            let (others, members) = split_by_fold(&items, &folds, fold);
+            assert_eq!(10, count(&members, 0));
+            assert_eq!(4, count(&members, 1));
+            assert_eq!(items.len(), others.len() + members.len());
+            assert!(in_order(&others) && in_order(&members));
+        }
+        assert!(folds.iter().all(|f| *f < 3));
+    }
+
+    #[test]
+    fn test_split() {
+        let items = labeled(&[(0, 40), (1, 20)]);
+        let (kept, held_out) = stratified_split(&items, 0.25, &mut StdRng::seed_from_u64(3));
+        assert_eq!((10, 5), (count(&held_out, 0), count(&held_out, 1)));
+        assert_eq!((30, 15), (count(&kept, 0), count(&kept, 1)));
+        assert!(held_out.iter().all(|item| !kept.contains(item)));
+        assert!(in_order(&kept) && in_order(&held_out));
+
+        let items = labeled(&[(0, 1), (1, 2)]);
+        let (kept, held_out) = stratified_split(&items, 0.9, &mut StdRng::seed_from_u64(3));
+        assert_eq!((1, 1), (count(&kept, 0), count(&kept, 1)));
+        assert_eq!((0, 1), (count(&held_out, 0), count(&held_out, 1)));
+    }
+}
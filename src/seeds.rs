@@ -0,0 +1,39 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::cache::checksum;
+
+/// Derives the seed of one random component of an experiment from the global seed, so
+/// that each component's random choices do not depend on which others were run first.
+pub fn derive_seed(seed: u64, component: &str) -> u64 {
+    checksum(format!("{}:{}", seed, component).as_bytes())
+}
+
+pub fn rng_for(seed: u64, component: &str) -> StdRng {
+    StdRng::seed_from_u64(derive_seed(seed, component))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brief::Descriptor;
+    use crate::convolutional::extract_kernels_from;
+    use crate::mnist_data::Image;
+
+    #[test]
+    fn test_derived() {
+        assert_eq!(derive_seed(7, "brief"), derive_seed(7, "brief"));
+        assert_ne!(derive_seed(7, "brief"), derive_seed(8, "brief"));
+        assert_ne!(derive_seed(7, "brief"), derive_seed(7, "uniform_brief"));
+
+        let brief_1 = Descriptor::classic_gaussian_brief(50, 28, 28, &mut rng_for(7, "brief"));
+        let brief_2 = Descriptor::classic_gaussian_brief(50, 28, 28, &mut rng_for(7, "brief"));
+        assert_eq!(brief_1.pairs(), brief_2.pairs());
+        let neighbors_1 = Descriptor::gaussian_neighbor(2, 4, 10, 8, &mut rng_for(3, "neighbors"));
+        let neighbors_2 = Descriptor::gaussian_neighbor(2, 4, 10, 8, &mut rng_for(3, "neighbors"));
+        assert_eq!(neighbors_1.pairs(), neighbors_2.pairs());
+
+        let images = vec![Image::from_pixels(4, 4, (0..16).map(|p| p * 15).collect())];
+        assert_eq!(extract_kernels_from(&images, 3, 2, &mut rng_for(5, "kernels")),
+                   extract_kernels_from(&images, 3, 2, &mut rng_for(5, "kernels")));
+    }
+}
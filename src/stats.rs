@@ -0,0 +1,60 @@
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample standard deviation, with Bessel's correction; zero for fewer than two values.
+pub fn stdev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        0.0
+    } else {
+        let m = mean(values);
+        (values.iter().map(|v| (v - m).powf(2.0)).sum::<f64>() / (values.len() - 1) as f64).sqrt()
+    }
+}
+
+/// Two-sided 95% critical values of Student's t distribution for 1 to 30 degrees of freedom.
+const T_95: [f64; 30] = [12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+    2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+    2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042];
+
+/// Rounds down to the nearest tabulated degrees of freedom, which errs on the wide side.
+pub fn t_critical_95(degrees_of_freedom: usize) -> f64 {
+    match degrees_of_freedom {
+        0 => f64::INFINITY,
+        1..=30 => T_95[degrees_of_freedom - 1],
+        31..=39 => 2.042,
+        40..=59 => 2.021,
+        60..=119 => 2.000,
+        _ => 1.980
+    }
+}
+
+/// The 95% confidence interval of the mean, based on Student's t distribution.
+pub fn confidence_interval_95(values: &[f64]) -> (f64, f64) {
+    let m = mean(values);
+    let half_width = if values.len() < 2 {
+        0.0
+    } else {
+        t_critical_95(values.len() - 1) * stdev(values) / (values.len() as f64).sqrt()
+    };
+    (m - half_width, m + half_width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(5.0, mean(&values));
+        assert!((stdev(&values) - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+        assert_eq!(0.0, stdev(&[3.0]));
+
+        let (low, high) = confidence_interval_95(&values);
+        let half_width = 2.365 * (32.0f64 / 7.0).sqrt() / 8.0f64.sqrt();
+        assert!((low - (5.0 - half_width)).abs() < 1e-12 && (high - (5.0 + half_width)).abs() < 1e-12);
+        assert_eq!((3.0, 3.0), confidence_interval_95(&[3.0]));
+        assert_eq!(1.980, t_critical_95(500));
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use crate::bias::BiasReport;
+
+/// The mean error rate of one variant under one combination of swept parameters, with
+/// its spatial bias when the images were also permuted.
+#[derive(Clone)]
+pub struct SweepCell {
+    pub original_error: f64,
+    pub bias: Option<BiasReport>,
+}
+
+/// Every combination of one value from each list, varying the last list fastest.
+pub fn combinations(lists: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    lists.iter().fold(vec![Vec::new()], |combinations, list| {
+        combinations.iter()
+            .flat_map(|combination| list.iter().map(move |value| {
+                let mut extended = combination.clone();
+                extended.push(*value);
+                extended
+            }))
+            .collect()
+    })
+}
+
+/// Error rates with a row for each combination of swept parameters and a column for each
+/// variant.
+pub struct SweepGrid {
+    parameters: Vec<String>,
+    rows: Vec<(Vec<usize>,BTreeMap<String,SweepCell>)>,
+}
+
+impl SweepGrid {
+    pub fn new(parameters: Vec<String>) -> SweepGrid {
+        SweepGrid {parameters, rows: Vec::new()}
+    }
+
+    pub fn add_row(&mut self, values: Vec<usize>, cells: BTreeMap<String,SweepCell>) {
+        self.rows.push((values, cells));
+    }
+
+    /// Cells give the original error rate, followed by the permuted error rate, the
+    /// difference and the bias when the images were permuted.
+    pub fn to_markdown(&self) -> String {
+        let mut variants: Vec<&String> = self.rows.iter().flat_map(|(_, cells)| cells.keys()).collect();
+        variants.sort();
+        variants.dedup();
+        let mut result = String::from("|");
+        self.parameters.iter().for_each(|parameter| result.push_str(&format!(" {} |", parameter)));
+        variants.iter().for_each(|variant| result.push_str(&format!(" {} |", variant)));
+        result.push_str("\n|");
+        self.parameters.iter().for_each(|_| result.push_str("---:|"));
+        variants.iter().for_each(|_| result.push_str("---:|"));
+        result.push('\n');
+        for (values, cells) in self.rows.iter() {
+            result.push('|');
+            values.iter().for_each(|value| result.push_str(&format!(" {} |", value)));
+            for variant in variants.iter() {
+                let cell = match cells.get(*variant) {
+                    None => "–".to_string(),
+                    Some(SweepCell {original_error, bias: None}) => format!("{:.2}", original_error),
+                    Some(SweepCell {original_error, bias: Some(report)}) =>
+                        format!("{:.2} / {:.2} ({:+.2}, {})", original_error, report.permuted_error, report.difference(), report.bias())
+                };
+                result.push_str(&format!(" {} |", cell));
+            }
+            result.push('\n');
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bias::Significance;
+
+    #[test]
+    fn test_combinations() {
+        assert_eq!(vec![vec![1, 5], vec![1, 6], vec![2, 5], vec![2, 6]], combinations(&[vec![1, 2], vec![5, 6]]));
+        assert_eq!(vec![Vec::<usize>::new()], combinations(&[]));
+    }
+
+    #[test]
+    fn test_grid() {
+        let mut grid = SweepGrid::new(vec!["k".to_string()]);
+        let mut cells = BTreeMap::new();
+        cells.insert("brief".to_string(), SweepCell {original_error: 10.0, bias: None});
+        grid.add_row(vec![3], cells);
+        let mut cells = BTreeMap::new();
+        let significance = Significance::PairedT {t: 5.0, critical: 2.0};
+        cells.insert("baseline".to_string(), SweepCell {original_error: 4.0,
+            bias: Some(BiasReport {original_error: 4.0, permuted_error: 6.5, significance})});
+        grid.add_row(vec![5], cells);
+        assert_eq!("| k | baseline | brief |\n\
+                    |---:|---:|---:|\n\
+                    | 3 | – | 10.00 |\n\
+                    | 5 | 4.00 / 6.50 (+2.50, positive bias) | – |\n", grid.to_markdown());
+    }
+}
@@ -8,10 +8,14 @@ pub fn time_milliseconds<N, F: FnMut() -> N>(mut f: F) -> (N, u128) {
 }
 
 pub fn print_time_milliseconds<N, F: FnMut() -> N>(label: &str, f: F) -> N {
+    print_and_time_milliseconds(label, f).0
+}
+
+pub fn print_and_time_milliseconds<N, F: FnMut() -> N>(label: &str, f: F) -> (N, u128) {
     println!("Started {}...", label);
     let result = time_milliseconds(f);
     let seconds = (result.1 / 1000) as f64;
     let milliseconds = ((result.1 % 1000) as f64) / 1000.0;
     println!("Finished {} after {} seconds", label, seconds + milliseconds);
-    result.0
+    result
 }
\ No newline at end of file
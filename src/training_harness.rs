@@ -1,42 +1,216 @@
 use crate::hash_histogram::HashHistogram;
-use std::{fmt, io};
+use crate::parallel;
+use std::fmt;
 use std::fmt::Formatter;
 use std::collections::HashSet;
-use std::io::Write;
 
+/// Counts of every (true label, classification) pair.
+#[derive(Clone)]
 pub struct ConfusionMatrix {
-    label_2_right: HashHistogram<u8>,
-    label_2_wrong: HashHistogram<u8>,
+    counts: HashHistogram<(u8,u8)>,
+    /// The margin of each classification recorded with one, and whether it was correct.
+    margins: Vec<(f64,bool)>,
+    /// The classifications abstained from, which are not counted.
+    rejected: usize,
 }
 
 impl ConfusionMatrix {
     pub fn new() -> ConfusionMatrix {
-        ConfusionMatrix {
-            label_2_right: HashHistogram::new(),
-            label_2_wrong: HashHistogram::new(),
-        }
+        ConfusionMatrix {counts: HashHistogram::new(), margins: Vec::new(), rejected: 0}
     }
 
     pub fn record(&mut self, img_label: u8, classification: u8) {
-        if classification == img_label {
-            self.label_2_right.bump(img_label);
-        } else {
-            self.label_2_wrong.bump(img_label);
+        self.counts.bump((img_label, classification));
+    }
+
+    /// Records the classification only if `reject_below` accepts it at `min_margin`, and
+    /// counts it as rejected otherwise. Its margin, as given by `margin`, is recorded either
+    /// way for `coverage_and_accuracy`.
+    pub fn record_or_reject(&mut self, img_label: u8, classification: u8, margin: f64, min_margin: f64) {
+        match reject_below((classification, margin), min_margin) {
+            Some(classification) => self.record(img_label, classification),
+            None => self.rejected += 1
+        }
+        self.margins.push((margin, img_label == classification));
+    }
+
+    /// Adds all of the counts, margins and rejections of `other` to this matrix.
+    pub fn merge(&mut self, other: &ConfusionMatrix) {
+        other.counts.all_labels().iter().for_each(|pair| self.counts.bump_by(*pair, other.counts.get(*pair)));
+        self.margins.extend(other.margins.iter().copied());
+        self.rejected += other.rejected;
+    }
+
+    /// The number of classifications abstained from by `record_or_reject`. The other
+    /// counts and the metrics cover only the accepted ones.
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+
+    /// With a reject option that abstains below `min_margin`, the fraction of the images
+    /// recorded with margins that would be classified, and the accuracy on those images.
+    /// The accuracy is zero when none would be.
+    pub fn coverage_and_accuracy(&self, min_margin: f64) -> (f64, f64) {
+        let accepted: Vec<bool> = self.margins.iter()
+            .filter(|(margin, _)| *margin >= min_margin)
+            .map(|(_, correct)| *correct)
+            .collect();
+        (ratio(accepted.len(), self.margins.len()), ratio(accepted.iter().filter(|correct| **correct).count(), accepted.len()))
+    }
+
+    /// `coverage_and_accuracy` for each of `min_margins`, one per line.
+    pub fn coverage_curve(&self, min_margins: &[f64]) -> String {
+        let mut result = String::new();
+        for min_margin in min_margins.iter() {
+            let (coverage, accuracy) = self.coverage_and_accuracy(*min_margin);
+            result.push_str(&format!("margin >= {:.2}: coverage {:.4}, accuracy {:.4}\n", min_margin, coverage, accuracy));
         }
+        result
+    }
+
+    pub fn count(&self, img_label: u8, classification: u8) -> usize {
+        self.counts.get((img_label, classification))
     }
 
+    /// The true labels of the recorded images.
     pub fn all_labels(&self) -> HashSet<u8> {
-        self.label_2_wrong.all_labels()
-            .union(&self.label_2_right.all_labels())
-            .copied()
-            .collect()
+        self.counts.all_labels().iter().map(|(label, _)| *label).collect()
+    }
+
+    /// Every label that occurs as either a true label or a classification, in order.
+    pub fn labels(&self) -> Vec<u8> {
+        let mut labels: Vec<u8> = self.counts.all_labels().iter()
+            .flat_map(|(label, classification)| vec![*label, *classification])
+            .collect::<HashSet<u8>>()
+            .into_iter()
+            .collect();
+        labels.sort_unstable();
+        labels
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.total_count()
+    }
+
+    pub fn total_correct(&self) -> usize {
+        self.labels().iter().map(|label| self.count(*label, *label)).sum()
+    }
+
+    pub fn right(&self, label: u8) -> usize {
+        self.count(label, label)
+    }
+
+    pub fn wrong(&self, label: u8) -> usize {
+        self.actual(label) - self.right(label)
+    }
+
+    /// The number of images whose true label is `label`.
+    pub fn actual(&self, label: u8) -> usize {
+        self.labels().iter().map(|classification| self.count(label, *classification)).sum()
+    }
+
+    /// The number of images classified as `label`.
+    pub fn predicted(&self, label: u8) -> usize {
+        self.labels().iter().map(|img_label| self.count(*img_label, label)).sum()
     }
 
     pub fn error_rate(&self) -> f64 {
-        let total_right = self.label_2_right.total_count() as f64;
-        let total_wrong = self.label_2_wrong.total_count() as f64;
+        let total_right = self.total_correct() as f64;
+        let total_wrong = (self.total() - self.total_correct()) as f64;
         total_wrong / (total_right + total_wrong)
     }
+
+    pub fn accuracy(&self) -> f64 {
+        self.total_correct() as f64 / self.total() as f64
+    }
+
+    /// Zero for a label that was never predicted.
+    pub fn precision(&self, label: u8) -> f64 {
+        ratio(self.right(label), self.predicted(label))
+    }
+
+    /// Zero for a label that never occurs.
+    pub fn recall(&self, label: u8) -> f64 {
+        ratio(self.right(label), self.actual(label))
+    }
+
+    pub fn f1(&self, label: u8) -> f64 {
+        harmonic_mean(self.precision(label), self.recall(label))
+    }
+
+    pub fn macro_precision(&self) -> f64 {
+        self.macro_average(|label| self.precision(label))
+    }
+
+    pub fn macro_recall(&self) -> f64 {
+        self.macro_average(|label| self.recall(label))
+    }
+
+    pub fn macro_f1(&self) -> f64 {
+        self.macro_average(|label| self.f1(label))
+    }
+
+    fn macro_average<F: Fn(u8) -> f64>(&self, metric: F) -> f64 {
+        let labels = self.labels();
+        labels.iter().map(|label| metric(*label)).sum::<f64>() / labels.len() as f64
+    }
+
+    /// With exactly one classification per image, micro-averaged precision, recall and F1
+    /// all equal the accuracy.
+    pub fn micro_f1(&self) -> f64 {
+        self.accuracy()
+    }
+
+    /// Cohen's kappa: agreement between true labels and classifications beyond chance.
+    pub fn kappa(&self) -> f64 {
+        let total = self.total() as f64;
+        let observed = self.accuracy();
+        let expected = self.labels().iter()
+            .map(|label| self.actual(*label) as f64 * self.predicted(*label) as f64)
+            .sum::<f64>() / (total * total);
+        if expected == 1.0 {1.0} else {(observed - expected) / (1.0 - expected)}
+    }
+
+    /// The full table, with a row for each true label and a column for each classification.
+    pub fn grid(&self) -> String {
+        let labels = self.labels();
+        let largest = labels.iter()
+            .flat_map(|img_label| labels.iter().map(move |classification| (*img_label, *classification)))
+            .map(|(img_label, classification)| self.count(img_label, classification))
+            .max()
+            .unwrap_or(0);
+        let width = largest.to_string().len().max(labels.last().map_or(1, |label| label.to_string().len()));
+        let mut result = format!("{:>w$} |", "", w = width);
+        labels.iter().for_each(|label| result.push_str(&format!(" {:>w$}", label, w = width)));
+        result.push('\n');
+        result.push_str(&"-".repeat(result.len() - 1));
+        result.push('\n');
+        for img_label in labels.iter() {
+            result.push_str(&format!("{:>w$} |", img_label, w = width));
+            labels.iter().for_each(|classification| result.push_str(&format!(" {:>w$}", self.count(*img_label, *classification), w = width)));
+            result.push('\n');
+        }
+        result
+    }
+
+    pub fn metrics(&self) -> String {
+        let mut result = String::new();
+        for label in self.labels() {
+            result.push_str(&format!("{}: precision {:.4}, recall {:.4}, F1 {:.4}\n", label, self.precision(label), self.recall(label), self.f1(label)));
+        }
+        result.push_str(&format!("macro: precision {:.4}, recall {:.4}, F1 {:.4}\n", self.macro_precision(), self.macro_recall(), self.macro_f1()));
+        result.push_str(&format!("micro: precision, recall and F1 {:.4}\n", self.micro_f1()));
+        result.push_str(&format!("kappa: {:.4}\n", self.kappa()));
+        result
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {0.0} else {numerator as f64 / denominator as f64}
+}
+
+fn harmonic_mean(a: f64, b: f64) -> f64 {
+    if a + b == 0.0 {0.0} else {2.0 * a * b / (a + b)}
 }
 
 impl fmt::Display for ConfusionMatrix {
@@ -44,7 +218,7 @@ impl fmt::Display for ConfusionMatrix {
         let mut ordered_labels: Vec<u8> = self.all_labels().iter().copied().collect();
         ordered_labels.sort_unstable();
         for label in ordered_labels {
-            writeln!(f, "{}: {} correct, {} incorrect", label, self.label_2_right.get(label), self.label_2_wrong.get(label))?;
+            writeln!(f, "{}: {} correct, {} incorrect", label, self.right(label), self.wrong(label))?;
         }
         Ok(())
     }
@@ -55,21 +229,58 @@ pub trait Classifier<I> {
 
     fn classify(&self, example: &I) -> u8;
 
-    fn test(&self, testing_images: &[(u8,I)]) -> ConfusionMatrix {
+    /// The score of each label `example` may have, from the most to the least likely. The
+    /// scores add up to 1. By default, the classification has all of it.
+    fn label_scores(&self, example: &I) -> Vec<(u8,f64)> {
+        vec![(self.classify(example), 1.0)]
+    }
+
+    /// The classification of `example`, and its `margin`.
+    fn classify_with_margin(&self, example: &I) -> (u8, f64) {
+        (self.classify(example), margin(&self.label_scores(example)))
+    }
+
+    /// The classification of `example`, or `None` to abstain when its margin is below
+    /// `min_margin`.
+    #[allow(dead_code)] // The experiments use reject_below, as they also need each image's margin.
+    fn classify_or_reject(&self, example: &I, min_margin: f64) -> Option<u8> {
+        reject_below(self.classify_with_margin(example), min_margin)
+    }
+
+    /// The confusion matrix of classifying `testing_images`, as in `classify_all`.
+    #[allow(dead_code)] // The experiments use classify_all, as they need each image's outcome as well.
+    fn test(&self, testing_images: &[(u8,I)]) -> ConfusionMatrix where Self: Sync, I: Sync {
         let mut result = ConfusionMatrix::new();
-        let mut count = 0;
-        let twentieth = testing_images.len() / 20;
-        for test_img in testing_images {
-            result.record(test_img.0, self.classify(&test_img.1));
-            count += 1;
-            if count % twentieth == 0 {
-                print!("{}%; ", count * 5 / twentieth);
-                io::stdout().flush().expect("Could not flush stdout");
-            }
+        for ((img_label, _), classification) in testing_images.iter().zip(self.classify_all(testing_images)) {
+            result.record(*img_label, classification);
         }
-        println!();
         result
     }
+
+    /// Classifies each image in order, reporting progress as it goes.
+    fn classify_all(&self, testing_images: &[(u8,I)]) -> Vec<u8> where Self: Sync, I: Sync {
+        parallel::map_with_progress(testing_images, 1, |(_, img)| self.classify(img))
+    }
+
+    /// `classify_with_margin` for each image, as in `classify_all`.
+    fn classify_all_with_margins(&self, testing_images: &[(u8,I)]) -> Vec<(u8, f64)> where Self: Sync, I: Sync {
+        parallel::map_with_progress(testing_images, 1, |(_, img)| self.classify_with_margin(img))
+    }
+}
+
+/// How far the score of the most likely label in `scores` is ahead of the next one.
+pub fn margin(scores: &[(u8,f64)]) -> f64 {
+    match scores {
+        [] => 0.0,
+        [(_, only)] => *only,
+        [(_, first), (_, second), ..] => first - second
+    }
+}
+
+/// The classification of a `(classification, margin)` pair, or `None` to abstain when the
+/// margin is below `min_margin`.
+pub fn reject_below((classification, margin): (u8, f64), min_margin: f64) -> Option<u8> {
+    if margin >= min_margin {Some(classification)} else {None}
 }
 
 #[cfg(test)]
@@ -103,4 +314,47 @@ mod tests {
 
         assert_eq!(format!("1: {} correct, {} incorrect\n2: {} correct, {} incorrect\n", one_ok, one_er, two_ok, two_er), matrix.to_string());
     }
+
+    #[test]
+    fn test_metrics() {
+        let mut matrix = ConfusionMatrix::new();
+        let table = [(0, 0, 5), (0, 1, 2), (0, 2, 1), (1, 1, 6), (1, 0, 1), (2, 2, 4), (2, 0, 1)];
+        for (img_label, classification, count) in table.iter() {
+            (0..*count).for_each(|_| matrix.record(*img_label, *classification));
+        }
+        assert_eq!(2, matrix.count(0, 1));
+        assert_eq!(0, matrix.count(1, 2));
+        assert_eq!(vec![0, 1, 2], matrix.labels());
+        assert_eq!((8, 7), (matrix.actual(0), matrix.predicted(0)));
+        assert!((matrix.error_rate() - 5.0 / 20.0).abs() < 1e-12);
+
+        assert!((matrix.precision(0) - 5.0 / 7.0).abs() < 1e-12);
+        assert!((matrix.recall(0) - 5.0 / 8.0).abs() < 1e-12);
+        assert!((matrix.f1(0) - 2.0 / 3.0).abs() < 1e-12);
+        assert!((matrix.macro_recall() - (5.0 / 8.0 + 6.0 / 7.0 + 4.0 / 5.0) / 3.0).abs() < 1e-12);
+        assert_eq!(0.75, matrix.micro_f1());
+        let expected = (8.0 * 7.0 + 7.0 * 8.0 + 5.0 * 5.0) / 400.0;
+        assert!((matrix.kappa() - (0.75 - expected) / (1.0 - expected)).abs() < 1e-12);
+
+        assert_eq!("  | 0 1 2\n---------\n0 | 5 2 1\n1 | 1 6 0\n2 | 1 0 4\n", matrix.grid());
+    }
+
+    #[test]
+    fn test_coverage() {
+        let mut matrix = ConfusionMatrix::new();
+        for (img_label, classification, margin) in [(0, 0, 1.0), (1, 1, 0.5), (1, 0, 0.1), (0, 0, 0.0)].iter() {
+            matrix.record_or_reject(*img_label, *classification, *margin, 0.5);
+        }
+        let mut other = ConfusionMatrix::new();
+        other.record_or_reject(1, 0, 0.75, 0.5);
+        matrix.merge(&other);
+        assert_eq!((3, 2), (matrix.total(), matrix.rejected()));
+        assert!((matrix.error_rate() - 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!((1.0, 0.6), matrix.coverage_and_accuracy(0.0));
+        assert_eq!((0.6, 2.0 / 3.0), matrix.coverage_and_accuracy(0.5));
+        assert_eq!((0.0, 0.0), matrix.coverage_and_accuracy(1.5));
+        assert_eq!("margin >= 0.90: coverage 0.2000, accuracy 1.0000\n", matrix.coverage_curve(&[0.9]));
+        assert_eq!(0.25, margin(&[(3, 0.5), (1, 0.25), (2, 0.25)]));
+        assert_eq!((Some(2), None), (reject_below((2, 0.5), 0.5), reject_below((2, 0.25), 0.5)));
+    }
 }
\ No newline at end of file
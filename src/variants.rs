@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use rand::Rng;
+use crate::brief::Descriptor;
+use crate::options::invalid_input;
+
+/// The number of pairs in a classic BRIEF descriptor.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pairs {
+    PerPixel(usize),
+    Total(usize),
+}
+
+impl Pairs {
+    pub fn count(&self, width: usize, height: usize) -> usize {
+        match self {
+            Pairs::PerPixel(n) => n * width * height,
+            Pairs::Total(n) => *n,
+        }
+    }
+}
+
+/// How images are converted before their distances are compared.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Features {
+    Raw,
+    UniformBrief {pairs: Pairs},
+    GaussianBrief {pairs: Pairs},
+    UniformNeighbors {neighbors: usize},
+    /// Neighbors are chosen with a standard deviation of `1/divisor` of the image's shorter side.
+    GaussianNeighbors {neighbors: usize, divisor: usize},
+    Patch {size: usize},
+    Convolutional {levels: usize},
+}
+
+impl Features {
+    /// Whether the features are generated randomly, so that they change from trial to trial.
+    pub fn is_random(&self) -> bool {
+        !matches!(self, Features::Raw | Features::Patch {..})
+    }
+
+    /// Whether the features are computed by a BRIEF `Descriptor`.
+    pub fn is_descriptor(&self) -> bool {
+        matches!(self, Features::UniformBrief {..} | Features::GaussianBrief {..} | Features::UniformNeighbors {..} | Features::GaussianNeighbors {..})
+    }
+
+    pub fn make_descriptor<R: Rng>(&self, width: usize, height: usize, rng: &mut R) -> Descriptor {
+        match *self {
+            Features::UniformBrief {pairs} => Descriptor::classic_uniform_brief(pairs.count(width, height), width, height, rng),
+            Features::GaussianBrief {pairs} => Descriptor::classic_gaussian_brief(pairs.count(width, height), width, height, rng),
+            Features::UniformNeighbors {neighbors} => Descriptor::uniform_neighbor(neighbors, width, height, rng),
+            Features::GaussianNeighbors {neighbors, divisor} => Descriptor::gaussian_neighbor(neighbors, width.min(height) / divisor, width, height, rng),
+            _ => panic!("{} features have no descriptor", self)
+        }
+    }
+
+    pub fn distances(&self) -> &'static [Distance] {
+        match self {
+            Features::Raw => &[Distance::Euclidean, Distance::Manhattan],
+            Features::Convolutional {..} => &[Distance::Euclidean],
+            _ => &[Distance::Hamming],
+        }
+    }
+}
+
+/// Written with the settings a configuration file would use, e.g. `patch size=3`.
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs = |pairs: &Pairs| match pairs {
+            Pairs::PerPixel(n) => format!("pairs-per-pixel={}", n),
+            Pairs::Total(n) => format!("pairs={}", n),
+        };
+        match self {
+            Features::Raw => write!(f, "raw"),
+            Features::UniformBrief {pairs: p} => write!(f, "uniform_brief {}", pairs(p)),
+            Features::GaussianBrief {pairs: p} => write!(f, "gaussian_brief {}", pairs(p)),
+            Features::UniformNeighbors {neighbors} => write!(f, "uniform_neighbors neighbors={}", neighbors),
+            Features::GaussianNeighbors {neighbors, divisor} => write!(f, "gaussian_neighbors neighbors={} divisor={}", neighbors, divisor),
+            Features::Patch {size} => write!(f, "patch size={}", size),
+            Features::Convolutional {levels} => write!(f, "convolutional levels={}", levels),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Distance {
+    Euclidean,
+    Manhattan,
+    Hamming,
+}
+
+impl fmt::Display for Distance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Distance::Euclidean => write!(f, "euclidean"),
+            Distance::Manhattan => write!(f, "manhattan"),
+            Distance::Hamming => write!(f, "hamming"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub features: Features,
+    pub distance: Distance,
+    pub k: usize,
+}
+
+impl Variant {
+    /// Uses the first distance that suits `features`.
+    pub fn new(name: &str, features: Features, k: usize) -> Variant {
+        Variant {name: name.to_string(), features, distance: features.distances()[0], k}
+    }
+
+    /// Builds a variant from the settings of a configuration file section. Settings that
+    /// are not given take their values from `defaults`.
+    pub fn from_settings(name: &str, settings: &BTreeMap<String,String>, defaults: &Defaults) -> io::Result<Variant> {
+        let number = |key: &str, default: usize| -> io::Result<usize> {
+            match settings.get(key) {
+                None => Ok(default),
+                Some(value) => value.parse().ok().filter(|n| *n > 0)
+                    .ok_or_else(|| invalid_input(format!("{} of variant {} must be a positive integer, not \"{}\"", key, name, value)))
+            }
+        };
+        let pairs = || -> io::Result<Pairs> {
+            match (settings.get("pairs"), settings.get("pairs-per-pixel")) {
+                (Some(_), Some(_)) => Err(invalid_input(format!("variant {} cannot give both pairs and pairs-per-pixel", name))),
+                (Some(_), None) => Ok(Pairs::Total(number("pairs", 1)?)),
+                (None, Some(_)) => Ok(Pairs::PerPixel(number("pairs-per-pixel", 1)?)),
+                (None, None) => Ok(defaults.pairs)
+            }
+        };
+
+        let extractor = settings.get("features").ok_or_else(|| invalid_input(format!("variant {} has no features", name)))?;
+        let (features, keys): (Features, &[&str]) = match extractor.as_str() {
+            "raw" => (Features::Raw, &[]),
+            "uniform_brief" => (Features::UniformBrief {pairs: pairs()?}, &["pairs", "pairs-per-pixel"]),
+            "gaussian_brief" => (Features::GaussianBrief {pairs: pairs()?}, &["pairs", "pairs-per-pixel"]),
+            "uniform_neighbors" => (Features::UniformNeighbors {neighbors: number("neighbors", defaults.neighbors)?}, &["neighbors"]),
+            "gaussian_neighbors" => (Features::GaussianNeighbors {neighbors: number("neighbors", defaults.neighbors)?,
+                                                                  divisor: number("divisor", defaults.gaussian_divisor)?}, &["neighbors", "divisor"]),
+            "patch" => (Features::Patch {size: number("size", defaults.patch_size)?}, &["size"]),
+            "convolutional" => (Features::Convolutional {levels: number("levels", 1)?}, &["levels"]),
+            _ => return Err(invalid_input(format!("variant {} has unknown features \"{}\"; expected one of {}", name, extractor, FEATURE_NAMES.join(", "))))
+        };
+        if let Some(key) = settings.keys().find(|key| !["features", "distance", "k"].contains(&key.as_str()) && !keys.contains(&key.as_str())) {
+            return Err(invalid_input(format!("variant {} has a setting \"{}\" that {} features do not use", name, key, extractor)));
+        }
+
+        let mut variant = Variant::new(name, features, number("k", defaults.k)?);
+        if let Some(distance) = settings.get("distance") {
+            variant.distance = *features.distances().iter().find(|d| d.to_string() == *distance)
+                .ok_or_else(|| invalid_input(format!("variant {} cannot use distance \"{}\" with {} features; expected one of {}", name, distance, extractor,
+                                                     features.distances().iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", "))))?;
+        }
+        Ok(variant)
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} distance={} k={}", self.features, self.distance, self.k)
+    }
+}
+
+pub const FEATURE_NAMES: [&str; 7] = ["raw", "uniform_brief", "gaussian_brief", "uniform_neighbors", "gaussian_neighbors", "patch", "convolutional"];
+
+/// Values for the settings that a variant does not give.
+pub struct Defaults {
+    pub k: usize,
+    pub pairs: Pairs,
+    pub neighbors: usize,
+    pub patch_size: usize,
+    pub gaussian_divisor: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Defaults {
+        Defaults {k: 7, pairs: Pairs::PerPixel(8), neighbors: 8, patch_size: 3, gaussian_divisor: 3}
+    }
+
+    fn settings(pairs: &[(&str, &str)]) -> BTreeMap<String,String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_from_settings() {
+        let variant = Variant::from_settings("g", &settings(&[("features", "gaussian_neighbors"), ("divisor", "5"), ("k", "3")]), &defaults()).unwrap();
+        assert_eq!(Variant {name: "g".to_string(), features: Features::GaussianNeighbors {neighbors: 8, divisor: 5}, distance: Distance::Hamming, k: 3}, variant);
+
+        let brief = Variant::from_settings("b", &settings(&[("features", "uniform_brief"), ("pairs", "100")]), &defaults()).unwrap();
+        assert_eq!(Features::UniformBrief {pairs: Pairs::Total(100)}, brief.features);
+        assert_eq!(100, Pairs::Total(100).count(28, 28));
+        assert_eq!(8 * 28 * 28, Pairs::PerPixel(8).count(28, 28));
+
+        let raw = Variant::from_settings("r", &settings(&[("features", "raw"), ("distance", "manhattan")]), &defaults()).unwrap();
+        assert_eq!((Features::Raw, Distance::Manhattan, 7), (raw.features, raw.distance, raw.k));
+        assert!(!raw.features.is_random() && brief.features.is_random());
+        assert_eq!("uniform_brief pairs=100 distance=hamming k=7", brief.to_string());
+
+        assert!(Variant::from_settings("x", &settings(&[("features", "patch"), ("distance", "euclidean")]), &defaults()).is_err());
+        assert!(Variant::from_settings("x", &settings(&[("features", "patch"), ("levels", "2")]), &defaults()).is_err());
+        assert!(Variant::from_settings("x", &settings(&[("features", "sift")]), &defaults()).is_err());
+        assert!(Variant::from_settings("x", &settings(&[("features", "raw"), ("k", "0")]), &defaults()).is_err());
+        assert!(Variant::from_settings("x", &settings(&[("distance", "hamming")]), &defaults()).is_err());
+    }
+}